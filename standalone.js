@@ -14,6 +14,17 @@ const rl = readline.createInterface({
   output: process.stdout
 });
 
+// Errors that end the search; anything else is reported and the search goes on.
+const FATAL_ERROR_CODES = new Set([
+  'invalid_input',
+  'invalid_criteria',
+  'missing_criteria',
+  'rare_output_unwritable',
+  'thread_pool_failed',
+  'rng_seed_failed',
+  'worker_panicked',
+]);
+
 const question = (query) => new Promise((resolve) => rl.question(query, resolve));
 
 const formatNumber = (num) => {
@@ -123,9 +134,19 @@ const startVanitySearch = async (prefix) => {
           await saveToFile(msg.address, msg.private_key, totalAttempts, elapsedSeconds, walletsPerSecond);
 
           process.exit(0);
+        } else if (msg.type === 'error') {
+          console.error(chalk.red(`\nError (${msg.code}): ${msg.message}`));
+          if (FATAL_ERROR_CODES.has(msg.code)) {
+            rustProcess.kill();
+            process.exit(1);
+          }
+        } else if (msg.type === 'warning') {
+          console.log(chalk.yellow(`Warning: ${msg.message}\n`));
+        } else if (msg.type === 'estimate') {
+          console.log(chalk.cyan(`Expected attempts for "${msg.prefix}": ~${formatNumber(msg.expected_attempts)}\n`));
         } else if (msg.type === 'rare') {
           await handleRareWallet(msg.address, msg.private_key, msg.pattern);
-        } else if (msg.type === 'progress') {
+        } else if (msg.type === 'progress' && msg.scope !== 'thread') {
           const totalAttempts = msg.attempts;
           const elapsed = Date.now() - startTime;
           const elapsedSeconds = elapsed / 1000;
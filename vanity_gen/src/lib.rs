@@ -0,0 +1,2818 @@
+//! Solana vanity address search.
+//!
+//! The `vanity_gen` binary drives this over a JSON-lines protocol on
+//! stdin/stdout; Rust callers can skip the process and call [`find_vanity`],
+//! or [`run_search`] for live events and progress.
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use bip39::Mnemonic;
+use ed25519_dalek::{Signer, Verifier};
+use hmac::{Hmac, Mac};
+use rand::rngs::{OsRng, SmallRng};
+use rand_chacha::ChaCha20Rng;
+use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use zeroize::Zeroizing;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub use ed25519_dalek::SigningKey;
+
+/// What a search looks for. Every supplied criterion has to match; the
+/// prefix criterion matches if the address starts with any of `prefixes`.
+#[derive(Clone, Default)]
+pub struct VanityCriteria {
+    /// A `?` in a prefix matches any base58 character, so `S?L` accepts
+    /// `SoL`, `S1L` and so on. A class such as `[Ss]` or `[a-f]` matches
+    /// any one of the characters it lists.
+    pub prefixes: Vec<String>,
+    pub suffix: Option<String>,
+    pub contains: Option<String>,
+    /// Rare-pattern rules, reported without ending the search.
+    pub patterns: Vec<PatternConfig>,
+}
+
+/// How a search runs.
+#[derive(Clone)]
+pub struct GenOptions {
+    /// Worker threads; defaults to one per CPU and is capped at twice that.
+    pub threads: Option<usize>,
+    pub max_attempts: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Matches to find before stopping.
+    pub count: u64,
+    pub key_format: KeyFormat,
+    /// Grind BIP39 mnemonics instead of raw keys, derived along
+    /// `derivation_path` or `m/44'/501'/0'/0'` if that is unset.
+    pub mnemonic: bool,
+    pub derivation_path: Option<String>,
+    /// Seed for reproducible runs.
+    ///
+    /// WARNING: anyone who knows the seed can regenerate every key the run
+    /// produced. Seeded mode exists for tests and audits only and must NEVER
+    /// be used to generate real wallets.
+    pub seed: Option<[u8; 32]>,
+    /// Where each worker draws its secret keys from.
+    pub rng: RngKind,
+    /// Search until stopped instead of for `count` matches, reporting only
+    /// the finds whose [`score_address`] beats every earlier one. Matches
+    /// and rare hits are ranked separately, so a rare hit never hides a
+    /// match.
+    pub keep_going: bool,
+    /// Reseed each worker's RNG from the OS after this many of its
+    /// candidates, as defense in depth for very long runs.
+    pub reseed_interval: Option<u64>,
+    /// Pins each worker to its own CPU for the length of the job, one per
+    /// physical core before any hyperthread sibling. Linux only; elsewhere it
+    /// logs a warning.
+    pub pin_threads: bool,
+    /// Parks workers while other processes compete for the CPUs, for searches
+    /// run as a background task. See [`run_search`] for the heuristic.
+    pub adaptive: bool,
+    /// Aborts the search once cancelled, from any thread.
+    pub cancel: CancelToken,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            max_attempts: None,
+            timeout: None,
+            count: 1,
+            key_format: KeyFormat::default(),
+            mnemonic: false,
+            derivation_path: None,
+            seed: None,
+            rng: RngKind::default(),
+            keep_going: false,
+            reseed_interval: None,
+            pin_threads: false,
+            adaptive: false,
+            cancel: CancelToken::default(),
+        }
+    }
+}
+
+/// Why a search could not start or run. `code` is the same string the
+/// binary reports in its `error` messages.
+#[derive(Debug, Clone)]
+pub struct VanityError {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl VanityError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for VanityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for VanityError {}
+
+/// A key that satisfied every criterion.
+#[derive(Clone)]
+pub struct VanityMatch {
+    pub address: String,
+    /// The keypair, encoded per `GenOptions::key_format`.
+    pub private_key: String,
+    pub signing_key: SigningKey,
+    /// Search-wide attempts when this key was generated.
+    pub attempts: u64,
+    pub elapsed_ms: u64,
+    /// Byte offset where `contains` matched, if it was set.
+    pub position: Option<usize>,
+    pub matched_prefix: Option<String>,
+    pub mnemonic: Option<String>,
+    /// [`score_address`] of `address`.
+    pub score: u32,
+}
+
+/// A key that fired a rare-pattern rule.
+#[derive(Clone)]
+pub struct RareHit {
+    pub address: String,
+    pub private_key: String,
+    pub pattern: String,
+    /// Which rule fired; its pattern string unless it was labelled.
+    pub label: String,
+    /// Byte offset where the pattern starts; unset for `count` rules,
+    /// which don't match in one place.
+    pub position: Option<usize>,
+    /// For `count` rules, how many times the pattern occurred.
+    pub count: Option<usize>,
+    pub attempts: u64,
+    pub elapsed_ms: u64,
+    /// The rule's `outputFile`, if it overrides the default rare file.
+    pub output_file: Option<PathBuf>,
+    /// [`score_address`] of `address`.
+    pub score: u32,
+}
+
+/// Reported by the workers of a running search.
+pub enum SearchEvent {
+    Found(VanityMatch),
+    Rare(RareHit),
+    /// A problem that did not stop the search by itself.
+    Error(VanityError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopReason {
+    Found,
+    Exhausted,
+    Timeout,
+    Cancelled,
+    /// The workers stopped without reaching any of the other outcomes.
+    Failed,
+}
+
+/// How a search ended.
+#[derive(Clone, Copy, Debug)]
+pub struct SearchSummary {
+    pub reason: StopReason,
+    pub attempts: u64,
+    pub matches: u64,
+    pub elapsed: Duration,
+}
+
+pub struct VanityOutcome {
+    pub matches: Vec<VanityMatch>,
+    pub rare: Vec<RareHit>,
+    pub summary: SearchSummary,
+}
+
+pub type VanityResult = Result<VanityOutcome, VanityError>;
+
+/// Shared flag for stopping a library search from another thread. Clones
+/// share the flag; a search sees it within about 50ms and ends as `Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cache-line aligned so adjacent per-thread counters don't false-share.
+#[repr(align(64))]
+#[derive(Default)]
+struct ThreadCounter(AtomicU64);
+
+/// Live counters for one search, so concurrent searches never see each
+/// other's work in their `attempts`.
+pub struct SearchStats {
+    attempts: AtomicU64,
+    matches: AtomicU64,
+    threads: Vec<ThreadCounter>,
+    /// Workers with a thread id at or past this are parked.
+    active_threads: AtomicUsize,
+    /// Highest `score_address` reported so far, for keep-going searches.
+    best_found_score: AtomicU32,
+    best_rare_score: AtomicU32,
+    /// Public keys already reported as matches, so no two threads report
+    /// the same one.
+    found_keys: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl SearchStats {
+    fn new(num_threads: usize) -> Self {
+        Self {
+            attempts: AtomicU64::new(0),
+            matches: AtomicU64::new(0),
+            threads: (0..num_threads).map(|_| ThreadCounter::default()).collect(),
+            active_threads: AtomicUsize::new(num_threads),
+            best_found_score: AtomicU32::new(0),
+            best_rare_score: AtomicU32::new(0),
+            found_keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    /// Attempts made by each worker, indexed by thread id.
+    pub fn thread_attempts(&self) -> impl Iterator<Item = u64> + '_ {
+        self.threads.iter().map(|counter| counter.0.load(Ordering::Relaxed))
+    }
+
+    /// Workers currently grinding; below the job's thread count only while
+    /// adaptive mode has parked some.
+    pub fn active_threads(&self) -> usize {
+        self.active_threads.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFormat {
+    /// Base58 of the 64-byte keypair, as accepted by Phantom and friends.
+    #[default]
+    Base58,
+    /// The `[u8; 64]` JSON array read by `solana-keygen` and the Solana CLI.
+    Json,
+}
+
+/// The generator each worker thread draws candidate secrets from.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RngKind {
+    /// ChaCha20, seeded once per thread from the OS.
+    #[default]
+    Chacha,
+    /// The OS generator for every candidate; slower, no state in process memory.
+    Os,
+    /// A small non-cryptographic PRNG, for benchmarking only.
+    ///
+    /// WARNING: its output is predictable. Keys it produces can be
+    /// recovered by others and must NEVER hold funds.
+    Fast,
+}
+
+impl RngKind {
+    /// A generator of this kind, from `seed` if given or else from the OS.
+    /// `Os` can't be seeded and ignores `seed`.
+    pub fn build(self, seed: Option<[u8; 32]>) -> Result<Box<dyn RngCore>, rand::Error> {
+        Ok(match (self, seed) {
+            (RngKind::Chacha, Some(seed)) => Box::new(ChaCha20Rng::from_seed(seed)),
+            (RngKind::Chacha, None) => Box::new(ChaCha20Rng::from_rng(OsRng)?),
+            (RngKind::Os, _) => Box::new(OsRng),
+            (RngKind::Fast, Some(seed)) => Box::new(SmallRng::from_seed(seed)),
+            (RngKind::Fast, None) => Box::new(SmallRng::from_rng(OsRng)?),
+        })
+    }
+}
+
+impl KeyFormat {
+    pub fn encode(self, secret: &[u8; 32], public: &[u8; 32]) -> String {
+        match self {
+            KeyFormat::Base58 => encode_private_key(secret, public),
+            KeyFormat::Json => encode_keypair_json(secret, public),
+        }
+    }
+
+    /// Inverse of `encode`, returning the 64-byte keypair.
+    pub fn decode(self, encoded: &str) -> Option<Zeroizing<[u8; 64]>> {
+        match self {
+            KeyFormat::Base58 => fd_bs58::decode_64(encoded).ok().map(Zeroizing::new),
+            KeyFormat::Json => {
+                let bytes: Zeroizing<Vec<u8>> = Zeroizing::new(serde_json::from_str(encoded).ok()?);
+                let mut keypair = Zeroizing::new([0u8; 64]);
+                if bytes.len() != keypair.len() {
+                    return None;
+                }
+                keypair.copy_from_slice(&bytes);
+                Some(keypair)
+            }
+        }
+    }
+}
+
+/// A set of base58 characters, bit `i` standing for `BASE58_ALPHABET[i]`.
+type CharSet = u64;
+
+/// Every base58 character; what a `?` in a prefix accepts.
+const ANY_BASE58: CharSet = (1 << 58) - 1;
+
+/// Longest possible base58 encoding of a 32-byte public key.
+pub const MAX_ADDRESS_LEN: usize = 44;
+
+/// How many times rarer than its length suggests a prefix must be before
+/// it gets a warning.
+const RARE_PREFIX_RATIO: f64 = 4.0;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Inverse of `BASE58_ALPHABET` over ASCII; `u8::MAX` marks non-base58 bytes.
+const BASE58_INDEX: [u8; 128] = {
+    let mut table = [u8::MAX; 128];
+    let mut i = 0;
+    while i < BASE58_ALPHABET.len() {
+        table[BASE58_ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+/// `m/44'/501'/0'/0'`, the path Phantom and `solana-keygen` derive by default.
+const SOLANA_DERIVATION_PATH: [u32; 4] = [44, 501, 0, 0];
+const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/// Signed and verified with every found keypair before it is reported.
+const SELF_TEST_MESSAGE: &[u8] = b"vanity_gen keypair self-test";
+
+/// How often `run_search` checks on its workers and calls `on_tick`.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+/// How often adaptive mode re-measures the per-worker rate.
+const ADAPT_INTERVAL: Duration = Duration::from_secs(2);
+/// Per-worker rate, as a fraction of the best seen, below which adaptive
+/// mode parks a worker.
+const SLOWDOWN_RATIO: f64 = 0.8;
+/// Per-worker rate, as a fraction of the best seen, above which adaptive
+/// mode wakes a parked worker.
+const RECOVERED_RATIO: f64 = 0.95;
+/// How long a parked worker sleeps between checks.
+const PARK_INTERVAL: Duration = Duration::from_millis(100);
+
+type HmacSha512 = Hmac<Sha512>;
+
+#[derive(Deserialize, Clone, Default)]
+pub struct PatternConfig {
+    #[serde(default)]
+    pub pattern: String,
+    /// Matched against the address instead of `pattern`; `minLength` and
+    /// `maxLength` then bound the length of the matched span.
+    pub regex: Option<String>,
+    /// Path to a word list, one word per line; fires when any word appears
+    /// in the address, case-sensitively.
+    pub words: Option<String>,
+    #[serde(rename = "minLength", default)]
+    pub min_length: usize,
+    /// Longest run (or count) this rule accepts; longer ones are left to other rules.
+    #[serde(rename = "maxLength")]
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub position: PatternPosition,
+    #[serde(default)]
+    pub kind: PatternConfigKind,
+    pub label: Option<String>,
+    /// Rare file for this rule's hits instead of the job-wide one.
+    #[serde(rename = "outputFile")]
+    pub output_file: Option<String>,
+    /// Set to false to keep a pattern in the config without using it.
+    pub enabled: Option<bool>,
+}
+
+impl PatternConfig {
+    /// What the rule looks for: its regex, word list path, kind for
+    /// `sequential` and `palindrome` rules, or else its pattern string.
+    pub fn name(&self) -> String {
+        let source = self.regex.as_ref().or(self.words.as_ref());
+        match (source, self.kind) {
+            (Some(source), _) => source.clone(),
+            (None, PatternConfigKind::Sequential) => "sequential".to_string(),
+            (None, PatternConfigKind::Palindrome) => "palindrome".to_string(),
+            (None, _) => self.pattern.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternConfigKind {
+    /// `minLength` consecutive repeats of the pattern.
+    #[default]
+    Run,
+    /// `minLength` occurrences anywhere in the address, not necessarily adjacent.
+    Count,
+    /// `minLength` characters climbing the base58 alphabet, like `abcd` or
+    /// `6789ABC`; `pattern` is ignored.
+    Sequential,
+    /// A stretch of at least `minLength` characters that reads the same
+    /// backwards; `pattern` is ignored.
+    Palindrome,
+}
+
+/// Where in the address a rare run has to sit to count.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternPosition {
+    Start,
+    End,
+    #[default]
+    Anywhere,
+}
+
+impl PatternPosition {
+    /// True if a run covering `start..end` of a `len`-byte address is allowed.
+    fn accepts(self, start: usize, end: usize, len: usize) -> bool {
+        match self {
+            PatternPosition::Start => start == 0,
+            PatternPosition::End => end == len,
+            PatternPosition::Anywhere => true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub patterns: Vec<PatternConfig>,
+}
+
+/// A prefix parsed for matching.
+struct PrefixPattern {
+    /// The trimmed prefix as given, for reporting.
+    source: String,
+    /// The characters allowed at each position of the address.
+    slots: Vec<CharSet>,
+}
+
+/// A validated search, precomputed for the hot loop. Build one with
+/// [`JobContext::new`] and run it with [`run_search`].
+pub struct JobContext {
+    prefixes: Vec<PrefixPattern>,
+    suffix_bytes: Option<Vec<u8>>,
+    contains_bytes: Option<Vec<u8>>,
+    pattern_rules: Option<Vec<PatternRule>>,
+    max_attempts: Option<u64>,
+    target_matches: u64,
+    key_format: KeyFormat,
+    /// Set in mnemonic mode: the hardened SLIP-0010 path to derive along.
+    derivation_path: Option<Vec<u32>>,
+    /// Deterministic RNG seed; see `GenOptions::seed` for why this is test-only.
+    seed: Option<[u8; 32]>,
+    rng: RngKind,
+    /// Builds each worker's RNG, and again on every reseed. Always
+    /// [`thread_rng`] outside tests.
+    rng_source: RngSource,
+    reseed_interval: Option<u64>,
+    keep_going: bool,
+    pin_threads: bool,
+    /// Per-prefix public key ranges, parallel to `prefixes`.
+    prefix_ranges: Vec<Vec<PubkeyRange>>,
+    /// For prefixes made only of `1`s, the number of leading zero bytes they
+    /// require; parallel to `prefixes`.
+    prefix_zero_bytes: Vec<Option<usize>>,
+    /// True when the prefix ranges alone decide a match, so candidates can be
+    /// rejected before paying for base58 encoding.
+    byte_prefilter: bool,
+    num_threads: usize,
+    adaptive: bool,
+    timeout: Option<Duration>,
+    cancel_token: CancelToken,
+    /// One `invalid_pattern` error per rare rule that was dropped.
+    skipped_patterns: Vec<VanityError>,
+    /// Prefixes that will take far longer than their length suggests.
+    prefix_warnings: Vec<String>,
+}
+
+/// Inclusive range of big-endian public keys whose address starts with a
+/// given prefix.
+type PubkeyRange = ([u8; 32], [u8; 32]);
+
+/// Details about how an address satisfied the job criteria.
+struct CriteriaMatch {
+    contains_offset: Option<usize>,
+    matched_prefix: Option<usize>,
+}
+
+enum PatternKind {
+    Single(u8),
+    Sequence(Vec<u8>),
+    Count(Vec<u8>),
+    Regex(regex::bytes::Regex),
+    Words(AhoCorasick),
+    Ascending,
+    Palindrome,
+}
+
+/// A rare rule that fired on an address.
+struct RareMatch {
+    pattern: String,
+    position: Option<usize>,
+    label: String,
+    count: Option<usize>,
+    output_file: Option<PathBuf>,
+}
+
+struct PatternRule {
+    kind: PatternKind,
+    min_length: usize,
+    max_length: Option<usize>,
+    position: PatternPosition,
+    label: String,
+    /// Overrides the job's rare-wallet file for hits on this rule.
+    output_file: Option<PathBuf>,
+}
+
+impl PatternRule {
+    fn accepts_length(&self, length: usize) -> bool {
+        length >= self.min_length && self.max_length.is_none_or(|max| length <= max)
+    }
+
+    fn hit(&self, pattern: String, position: Option<usize>, count: Option<usize>) -> RareMatch {
+        RareMatch {
+            pattern,
+            position,
+            label: self.label.clone(),
+            count,
+            output_file: self.output_file.clone(),
+        }
+    }
+}
+
+impl JobContext {
+    /// Validates `criteria` and `options` into a runnable search.
+    ///
+    /// Rare rules that can't be used don't fail the search; they are left
+    /// out and listed in `skipped_patterns`.
+    pub fn new(criteria: &VanityCriteria, options: &GenOptions) -> Result<Self, VanityError> {
+        // Pasted criteria often carry stray spaces or newlines that would never match.
+        let prefixes = criteria
+            .prefixes
+            .iter()
+            .map(|p| {
+                let source = p.trim();
+                parse_prefix(source)
+                    .map(|slots| PrefixPattern {
+                        source: source.to_string(),
+                        slots,
+                    })
+                    .map_err(|message| VanityError::new("invalid_criteria", message))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let suffix = criteria.suffix.as_deref().map(str::trim);
+        let contains = criteria.contains.as_deref().map(str::trim);
+        validate_criteria(&prefixes, suffix, contains)?;
+
+        let suffix_bytes = suffix.map(|s| s.as_bytes().to_vec());
+        let contains_bytes = contains.map(|c| c.as_bytes().to_vec());
+        let mut skipped_patterns = Vec::new();
+        let pattern_rules = preprocess_patterns(&criteria.patterns, &mut skipped_patterns);
+        let byte_prefilter = !prefixes.is_empty()
+            && suffix_bytes.is_none()
+            && contains_bytes.is_none()
+            && pattern_rules.is_none();
+        if options.seed.is_some() && options.rng == RngKind::Os {
+            return Err(VanityError::new(
+                "invalid_rng",
+                "the os RNG can't be seeded; use chacha or fast with a seed",
+            ));
+        }
+        match options.reseed_interval {
+            Some(0) => {
+                return Err(VanityError::new("invalid_rng", "reseed interval must be at least 1"))
+            }
+            Some(_) if options.seed.is_some() => {
+                return Err(VanityError::new(
+                    "invalid_rng",
+                    "reseeding from the OS would make a seeded run unreproducible",
+                ))
+            }
+            _ => {}
+        }
+        let derivation_path = match options.derivation_path.as_deref() {
+            Some(path) => Some(
+                parse_derivation_path(path)
+                    .map_err(|message| VanityError::new("invalid_derivation_path", message))?,
+            ),
+            None => options.mnemonic.then(|| SOLANA_DERIVATION_PATH.to_vec()),
+        };
+
+        // Only the literal head of a prefix, up to its first `?` or class,
+        // can narrow down the key bytes.
+        let literal_heads: Vec<String> = prefixes.iter().map(|p| literal_head(&p.slots)).collect();
+        let prefix_ranges: Vec<Vec<PubkeyRange>> =
+            literal_heads.iter().map(|p| prefix_pubkey_ranges(p)).collect();
+        let prefix_zero_bytes: Vec<Option<usize>> =
+            literal_heads.iter().map(|p| leading_zero_prefix_len(p)).collect();
+        let mut prefix_warnings = Vec::new();
+        for (i, prefix) in prefixes.iter().enumerate() {
+            // All-`1` heads are exact zero-byte checks with no ranges to inspect.
+            if prefix_zero_bytes[i].is_none() {
+                prefix_warnings.extend(check_leading_bytes(
+                    &prefix.source,
+                    &literal_heads[i],
+                    &prefix_ranges[i],
+                )?);
+            }
+        }
+        let context = Self {
+            prefixes,
+            suffix_bytes,
+            contains_bytes,
+            pattern_rules,
+            max_attempts: options.max_attempts,
+            target_matches: options.count.max(1),
+            key_format: options.key_format,
+            derivation_path,
+            seed: options.seed,
+            rng: options.rng,
+            rng_source: thread_rng,
+            reseed_interval: options.reseed_interval,
+            keep_going: options.keep_going,
+            pin_threads: options.pin_threads,
+            prefix_ranges,
+            prefix_zero_bytes,
+            byte_prefilter,
+            num_threads: resolve_thread_count(options.threads),
+            adaptive: options.adaptive,
+            timeout: options.timeout,
+            cancel_token: options.cancel.clone(),
+            skipped_patterns,
+            prefix_warnings,
+        };
+        if !context.has_criteria() {
+            return Err(VanityError::new(
+                "missing_criteria",
+                "job needs at least one of prefix, prefixes, suffix or contains",
+            ));
+        }
+        Ok(context)
+    }
+
+    /// The trimmed prefixes, in the order they were given.
+    pub fn prefixes(&self) -> Vec<String> {
+        self.prefixes.iter().map(|p| p.source.clone()).collect()
+    }
+
+    pub fn threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub fn max_attempts(&self) -> Option<u64> {
+        self.max_attempts
+    }
+
+    pub fn target_matches(&self) -> u64 {
+        self.target_matches
+    }
+
+    pub fn skipped_patterns(&self) -> &[VanityError] {
+        &self.skipped_patterns
+    }
+
+    /// One message per prefix that only fits keys with a particular first
+    /// byte, making it much rarer than its length suggests.
+    pub fn prefix_warnings(&self) -> &[String] {
+        &self.prefix_warnings
+    }
+
+    /// The `outputFile` of each rare rule in use, `None` for rules that
+    /// save to the default rare file.
+    pub fn rare_output_files(&self) -> impl Iterator<Item = Option<&Path>> + '_ {
+        self.pattern_rules
+            .iter()
+            .flatten()
+            .map(|rule| rule.output_file.as_deref())
+    }
+
+    /// True if no prefix can match `public_key`, decided from the raw bytes.
+    fn rejects_public_key(&self, public_key: &[u8; 32]) -> bool {
+        self.byte_prefilter
+            && !self
+                .prefix_ranges
+                .iter()
+                .zip(&self.prefix_zero_bytes)
+                .any(|(ranges, zero_bytes)| match zero_bytes {
+                    // Cheapest case: `1`s map one-to-one onto leading zero bytes.
+                    Some(count) => public_key[..*count].iter().all(|&b| b == 0),
+                    None => ranges
+                        .iter()
+                        .any(|(low, high)| low <= public_key && public_key <= high),
+                })
+    }
+
+    fn has_criteria(&self) -> bool {
+        !self.prefixes.is_empty() || self.suffix_bytes.is_some() || self.contains_bytes.is_some()
+    }
+
+    /// Checks every supplied criterion; criteria that were not supplied always pass.
+    fn match_address(&self, address_bytes: &[u8]) -> Option<CriteriaMatch> {
+        let matched_prefix = if self.prefixes.is_empty() {
+            None
+        } else {
+            Some(
+                self.prefixes
+                    .iter()
+                    .position(|prefix| prefix_matches(&prefix.slots, address_bytes))?,
+            )
+        };
+
+        if let Some(suffix) = &self.suffix_bytes {
+            if !address_bytes.ends_with(suffix) {
+                return None;
+            }
+        }
+
+        let contains_offset = match &self.contains_bytes {
+            Some(needle) => Some(
+                address_bytes
+                    .windows(needle.len())
+                    .position(|window| window == needle.as_slice())?,
+            ),
+            None => None,
+        };
+
+        Some(CriteriaMatch {
+            contains_offset,
+            matched_prefix,
+        })
+    }
+
+    fn prefix_string(&self, index: usize) -> String {
+        self.prefixes[index].source.clone()
+    }
+}
+
+/// The leading slots that allow exactly one character, as a string.
+fn literal_head(slots: &[CharSet]) -> String {
+    slots
+        .iter()
+        .take_while(|slot| slot.count_ones() == 1)
+        .map(|slot| BASE58_ALPHABET[slot.trailing_zeros() as usize] as char)
+        .collect()
+}
+
+/// True if each of the first bytes of `address` is in its prefix slot.
+fn prefix_matches(slots: &[CharSet], address: &[u8]) -> bool {
+    address.len() >= slots.len()
+        && slots
+            .iter()
+            .zip(address)
+            .all(|(slot, &byte)| base58_index(byte).is_some_and(|i| slot & (1 << i) != 0))
+}
+
+/// Parses a prefix into the characters allowed at each position: a literal,
+/// `?` for any character, or a class such as `[Ss]` or `[a-f]`.
+fn parse_prefix(prefix: &str) -> Result<Vec<CharSet>, String> {
+    let mut slots = Vec::new();
+    let mut rest = prefix;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        let slot = match c {
+            '?' => ANY_BASE58,
+            '[' => {
+                let end = rest
+                    .find(']')
+                    .ok_or_else(|| format!("prefix {:?} has an unclosed '['", prefix))?;
+                let class = &rest[..end];
+                rest = &rest[end + 1..];
+                parse_char_class(class).map_err(|reason| {
+                    format!("prefix {:?} has a malformed class [{}]: {}", prefix, class, reason)
+                })?
+            }
+            ']' => return Err(format!("prefix {:?} has a ']' without a '['", prefix)),
+            c => base58_char_set(c).ok_or_else(|| {
+                format!(
+                    "prefix {:?} contains {:?}, which never appears in a base58 address",
+                    prefix, c
+                )
+            })?,
+        };
+        slots.push(slot);
+    }
+    Ok(slots)
+}
+
+/// Parses the inside of a `[...]` class: characters and `a-z` style ranges,
+/// a range keeping only the base58 characters between its ends.
+fn parse_char_class(class: &str) -> Result<CharSet, String> {
+    if class.is_empty() {
+        return Err("it lists no characters".to_string());
+    }
+    let mut set = 0;
+    let mut chars = class.chars().peekable();
+    while let Some(first) = chars.next() {
+        let last = match chars.next_if_eq(&'-') {
+            Some(_) => chars.next().ok_or("a range is missing its end")?,
+            None => first,
+        };
+        if let Some(c) = [first, last].into_iter().find(|&c| base58_char_set(c).is_none()) {
+            return Err(format!("{:?} never appears in a base58 address", c));
+        }
+        if first > last {
+            return Err(format!("range {}-{} is backwards", first, last));
+        }
+        set |= (first..=last).filter_map(base58_char_set).fold(0, |set, c| set | c);
+    }
+    Ok(set)
+}
+
+/// The single-character set for `c`, if it is a base58 character.
+fn base58_char_set(c: char) -> Option<CharSet> {
+    u8::try_from(c).ok().and_then(base58_index).map(|i| 1 << i)
+}
+
+/// Rejects criteria that could never match, or that would match every address.
+fn validate_criteria(
+    prefixes: &[PrefixPattern],
+    suffix: Option<&str>,
+    contains: Option<&str>,
+) -> Result<(), VanityError> {
+    // Each criterion with the number of address characters it covers.
+    let criteria: Vec<(&str, &str, usize)> = prefixes
+        .iter()
+        .map(|p| ("prefix", p.source.as_str(), p.slots.len()))
+        .chain(suffix.map(|s| ("suffix", s, s.len())))
+        .chain(contains.map(|c| ("contains", c, c.len())))
+        .collect();
+
+    // An empty criterion matches every address, so the first key would "win".
+    if let Some((field, _, _)) = criteria.iter().find(|(_, _, len)| *len == 0) {
+        return Err(VanityError::new(
+            "invalid_criteria",
+            format!("{} must be non-empty", field),
+        ));
+    }
+
+    if let Some((field, value, len)) = criteria.iter().find(|(_, _, len)| *len > MAX_ADDRESS_LEN) {
+        return Err(VanityError::new(
+            "invalid_criteria",
+            format!(
+                "{} {:?} is {} characters, but Solana addresses are at most {}",
+                field, value, len, MAX_ADDRESS_LEN
+            ),
+        ));
+    }
+
+    if let Some(prefix) = prefixes
+        .iter()
+        .find(|p| p.slots.iter().all(|&slot| slot == ANY_BASE58))
+    {
+        return Err(VanityError::new(
+            "invalid_criteria",
+            format!(
+                "prefix {:?} is only wildcards and would match every address",
+                prefix.source
+            ),
+        ));
+    }
+
+    // Prefixes were checked character by character when they were parsed.
+    let invalid_criterion = criteria
+        .iter()
+        .filter(|(field, _, _)| *field != "prefix")
+        .find_map(|&(field, value, _)| {
+            is_valid_base58(value)
+                .err()
+                .map(|invalid| (field, value, invalid))
+        });
+    if let Some((field, value, invalid)) = invalid_criterion {
+        return Err(VanityError::new(
+            "invalid_criteria",
+            format!(
+                "{} {:?} contains {:?}, which never appears in a base58 address",
+                field, value, invalid
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn preprocess_patterns(
+    patterns: &[PatternConfig],
+    skipped: &mut Vec<VanityError>,
+) -> Option<Vec<PatternRule>> {
+    let rules: Vec<PatternRule> = patterns
+        .iter()
+        .filter_map(|pattern| pattern_rule(pattern, skipped))
+        .collect();
+    if rules.is_empty() {
+        None
+    } else {
+        Some(rules)
+    }
+}
+
+/// The config patterns a job would actually use: enabled, and not skipped
+/// as invalid.
+pub fn usable_patterns(patterns: &[PatternConfig]) -> Vec<&PatternConfig> {
+    patterns
+        .iter()
+        .filter(|pattern| pattern_rule(pattern, &mut Vec::new()).is_some())
+        .collect()
+}
+
+/// Compiles one config pattern, or records in `skipped` why it can't be
+/// used. Disabled and empty patterns are dropped without a note.
+fn pattern_rule(pattern: &PatternConfig, skipped: &mut Vec<VanityError>) -> Option<PatternRule> {
+    if !pattern.enabled.unwrap_or(true) {
+        return None;
+    }
+    let name = pattern.name();
+    if let Some(max_length) = pattern.max_length.filter(|&max| max < pattern.min_length) {
+        skipped.push(VanityError::new(
+            "invalid_pattern",
+            format!(
+                "pattern {:?} has maxLength {} below its minLength {} and was skipped",
+                name, max_length, pattern.min_length
+            ),
+        ));
+        return None;
+    }
+
+    let kind = if let Some(regex) = &pattern.regex {
+        match regex::bytes::Regex::new(regex) {
+            Ok(regex) => PatternKind::Regex(regex),
+            Err(err) => {
+                skipped.push(VanityError::new(
+                    "invalid_pattern",
+                    format!("regex {:?} does not compile and was skipped: {}", regex, err),
+                ));
+                return None;
+            }
+        }
+    } else if let Some(path) = &pattern.words {
+        match load_word_matcher(path) {
+            Ok(matcher) => PatternKind::Words(matcher),
+            Err(err) => {
+                skipped.push(VanityError::new(
+                    "invalid_pattern",
+                    format!("word list {} could not be loaded and was skipped: {}", path, err),
+                ));
+                return None;
+            }
+        }
+    } else {
+        let needs_pattern = !matches!(
+            pattern.kind,
+            PatternConfigKind::Sequential | PatternConfigKind::Palindrome
+        );
+        if pattern.pattern.is_empty() && needs_pattern {
+            return None;
+        }
+        // A zero-length run matches every address.
+        if pattern.min_length == 0 {
+            skipped.push(VanityError::new(
+                "invalid_pattern",
+                format!("pattern {:?} has minLength 0 and was skipped", name),
+            ));
+            return None;
+        }
+
+        let bytes = pattern.pattern.as_bytes();
+        match pattern.kind {
+            PatternConfigKind::Count => PatternKind::Count(bytes.to_vec()),
+            PatternConfigKind::Run if bytes.len() == 1 => PatternKind::Single(bytes[0]),
+            PatternConfigKind::Run => PatternKind::Sequence(bytes.to_vec()),
+            PatternConfigKind::Sequential => PatternKind::Ascending,
+            PatternConfigKind::Palindrome => PatternKind::Palindrome,
+        }
+    };
+
+    Some(PatternRule {
+        kind,
+        min_length: pattern.min_length,
+        max_length: pattern.max_length,
+        position: pattern.position,
+        label: pattern.label.clone().unwrap_or(name),
+        output_file: pattern.output_file.as_ref().map(PathBuf::from),
+    })
+}
+
+/// Builds a substring matcher over the words in `path`. Blank lines, `#`
+/// comments and words that can never appear in base58 are skipped.
+fn load_word_matcher(path: &str) -> Result<AhoCorasick, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let words: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .filter(|word| is_valid_base58(word).is_ok())
+        .collect();
+    if words.is_empty() {
+        return Err("no usable base58 words".to_string());
+    }
+
+    AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(&words)
+        .map_err(|err| err.to_string())
+}
+
+fn find_rare_pattern(address_bytes: &[u8], job_context: &JobContext) -> Option<RareMatch> {
+    let rules = job_context.pattern_rules.as_ref()?;
+
+    for rule in rules {
+        match &rule.kind {
+            PatternKind::Single(target) => {
+                let len = address_bytes.len();
+                let mut repeat_count = 0;
+                for (index, &byte) in address_bytes.iter().enumerate() {
+                    if byte == *target {
+                        repeat_count += 1;
+                        continue;
+                    }
+
+                    if rule.accepts_length(repeat_count)
+                        && rule.position.accepts(index - repeat_count, index, len)
+                    {
+                        let pattern = vec![*target; repeat_count];
+                        if let Ok(found_pattern) = String::from_utf8(pattern) {
+                            return Some(rule.hit(found_pattern, Some(index - repeat_count), None));
+                        }
+                    }
+                    repeat_count = 0;
+                }
+
+                if rule.accepts_length(repeat_count)
+                    && rule.position.accepts(len - repeat_count, len, len)
+                {
+                    let pattern = vec![*target; repeat_count];
+                    if let Ok(found_pattern) = String::from_utf8(pattern) {
+                        return Some(rule.hit(found_pattern, Some(len - repeat_count), None));
+                    }
+                }
+            }
+            PatternKind::Sequence(pattern_bytes) => {
+                let pattern_len = pattern_bytes.len();
+                if pattern_len == 0 || address_bytes.len() < pattern_len * rule.min_length {
+                    continue;
+                }
+
+                let mut index = 0;
+                while index + pattern_len <= address_bytes.len() {
+                    if &address_bytes[index..index + pattern_len] == pattern_bytes {
+                        let mut match_count = 1;
+                        let mut cursor = index + pattern_len;
+
+                        while cursor + pattern_len <= address_bytes.len()
+                            && &address_bytes[cursor..cursor + pattern_len] == pattern_bytes
+                        {
+                            match_count += 1;
+                            cursor += pattern_len;
+                        }
+
+                        if rule.accepts_length(match_count)
+                            && rule.position.accepts(index, cursor, address_bytes.len())
+                        {
+                            let mut repeated = Vec::with_capacity(pattern_len * match_count);
+                            for _ in 0..match_count {
+                                repeated.extend_from_slice(pattern_bytes);
+                            }
+
+                            if let Ok(found_pattern) = String::from_utf8(repeated) {
+                                return Some(rule.hit(found_pattern, Some(index), None));
+                            }
+                        }
+
+                        index = cursor;
+                        continue;
+                    }
+
+                    index += 1;
+                }
+            }
+            PatternKind::Regex(regex) => {
+                let len = address_bytes.len();
+                let span = regex.find_iter(address_bytes).find(|span| {
+                    rule.accepts_length(span.len())
+                        && rule.position.accepts(span.start(), span.end(), len)
+                });
+                if let Some(span) = span {
+                    if let Ok(found_pattern) = String::from_utf8(span.as_bytes().to_vec()) {
+                        return Some(rule.hit(found_pattern, Some(span.start()), None));
+                    }
+                }
+            }
+            PatternKind::Words(matcher) => {
+                let len = address_bytes.len();
+                let word = matcher.find_iter(address_bytes).find(|word| {
+                    rule.accepts_length(word.len())
+                        && rule.position.accepts(word.start(), word.end(), len)
+                });
+                if let Some(word) = word {
+                    let found_word = address_bytes[word.range()].to_vec();
+                    if let Ok(found_pattern) = String::from_utf8(found_word) {
+                        return Some(rule.hit(found_pattern, Some(word.start()), None));
+                    }
+                }
+            }
+            PatternKind::Ascending => {
+                let len = address_bytes.len();
+                let mut start = 0;
+                for end in 1..=len {
+                    let climbs = end < len
+                        && base58_index(address_bytes[end])
+                            .zip(base58_index(address_bytes[end - 1]))
+                            .is_some_and(|(current, previous)| current == previous + 1);
+                    if climbs {
+                        continue;
+                    }
+
+                    if rule.accepts_length(end - start) && rule.position.accepts(start, end, len) {
+                        let run = address_bytes[start..end].to_vec();
+                        if let Ok(found_pattern) = String::from_utf8(run) {
+                            return Some(rule.hit(found_pattern, Some(start), None));
+                        }
+                    }
+                    start = end;
+                }
+            }
+            PatternKind::Palindrome => {
+                if let Some((start, end)) = find_palindrome(address_bytes, rule) {
+                    let window = address_bytes[start..end].to_vec();
+                    if let Ok(found_pattern) = String::from_utf8(window) {
+                        return Some(rule.hit(found_pattern, Some(start), None));
+                    }
+                }
+            }
+            PatternKind::Count(pattern_bytes) => {
+                let occurrences = count_occurrences(address_bytes, pattern_bytes);
+                if rule.accepts_length(occurrences) {
+                    if let Ok(found_pattern) = String::from_utf8(pattern_bytes.clone()) {
+                        return Some(rule.hit(found_pattern, None, Some(occurrences)));
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// First maximal palindromic window of `bytes` that `rule` accepts, found by
+/// expanding around every odd and even center.
+fn find_palindrome(bytes: &[u8], rule: &PatternRule) -> Option<(usize, usize)> {
+    let len = bytes.len();
+    for center in 0..2 * len {
+        let (mut start, mut end) = (center / 2, center / 2 + center % 2);
+        while start > 0 && end < len && bytes[start - 1] == bytes[end] {
+            start -= 1;
+            end += 1;
+        }
+        if rule.accepts_length(end - start) && rule.position.accepts(start, end, len) {
+            return Some((start, end));
+        }
+    }
+    None
+}
+
+/// Non-overlapping occurrences of `pattern` anywhere in `haystack`.
+fn count_occurrences(haystack: &[u8], pattern: &[u8]) -> usize {
+    if pattern.len() == 1 {
+        return haystack.iter().filter(|&&byte| byte == pattern[0]).count();
+    }
+
+    let mut count = 0;
+    let mut index = 0;
+    while index + pattern.len() <= haystack.len() {
+        if &haystack[index..index + pattern.len()] == pattern {
+            count += 1;
+            index += pattern.len();
+        } else {
+            index += 1;
+        }
+    }
+    count
+}
+
+/// Base58-encodes a 32-byte public key into `buf`, the same as
+/// `fd_bs58::encode_32` but without allocating.
+pub fn encode_address<'a>(public_key: &[u8; 32], buf: &'a mut [u8; MAX_ADDRESS_LEN]) -> &'a str {
+    use fd_bs58::constants::{ENC_TABLE_32, R1_DIV};
+
+    // Firedancer's method: spread each 32-bit limb over base-58^5 terms
+    // with a table of powers, then carry once, instead of dividing the
+    // whole number by 58^5 nine times.
+    let mut terms = [0u64; 9];
+    for (bytes, powers) in public_key.chunks_exact(4).zip(&ENC_TABLE_32) {
+        let limb = u64::from(u32::from_be_bytes(bytes.try_into().expect("chunks are 4 bytes")));
+        for (term, &power) in terms[1..].iter_mut().zip(powers) {
+            *term += limb * power;
+        }
+    }
+    for i in (1..terms.len()).rev() {
+        terms[i - 1] += terms[i] / R1_DIV;
+        terms[i] %= R1_DIV;
+    }
+    let mut digits = [0u8; 45];
+    for (chunk_digits, &term) in digits.chunks_exact_mut(5).zip(&terms) {
+        // Each term is below 58^5 < 2^32; u32 arithmetic is cheaper here.
+        let mut term = term as u32;
+        for digit in chunk_digits.iter_mut().rev() {
+            *digit = (term % 58) as u8;
+            term /= 58;
+        }
+    }
+
+    // Each leading zero byte is a `1`; the value itself has no leading zeros.
+    let zeros = public_key.iter().take_while(|&&b| b == 0).count();
+    let significant = digits.iter().position(|&d| d != 0).unwrap_or(digits.len());
+    let len = zeros + digits.len() - significant;
+    buf[..zeros].fill(b'1');
+    for (out, &digit) in buf[zeros..len].iter_mut().zip(&digits[significant..]) {
+        *out = BASE58_ALPHABET[digit as usize];
+    }
+    std::str::from_utf8(&buf[..len]).expect("base58 is ASCII")
+}
+
+/// Position of `byte` in the base58 alphabet.
+fn base58_index(byte: u8) -> Option<u8> {
+    match BASE58_INDEX.get(byte as usize) {
+        Some(&index) if index != u8::MAX => Some(index),
+        _ => None,
+    }
+}
+
+/// Returns the first character of `s` that cannot appear in a base58 address.
+fn is_valid_base58(s: &str) -> Result<(), char> {
+    match s
+        .chars()
+        .find(|&c| !c.is_ascii() || !BASE58_ALPHABET.contains(&(c as u8)))
+    {
+        Some(invalid) => Err(invalid),
+        None => Ok(()),
+    }
+}
+
+/// Little-endian 320-bit integer: enough headroom for `58^44`, which just
+/// exceeds the 256-bit key space.
+type Wide = [u64; 5];
+
+fn wide_mul_add(value: &mut Wide, mul: u64, add: u64) {
+    let mut carry = add as u128;
+    for limb in value.iter_mut() {
+        let product = *limb as u128 * mul as u128 + carry;
+        *limb = product as u64;
+        carry = product >> 64;
+    }
+}
+
+fn wide_pow2(bits: usize) -> Wide {
+    let mut value = [0u64; 5];
+    value[bits / 64] = 1 << (bits % 64);
+    value
+}
+
+fn wide_cmp(a: &Wide, b: &Wide) -> std::cmp::Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// Big-endian bytes of `value - 1`; the caller guarantees `0 < value <= 2^256`.
+fn wide_pred_to_bytes(value: &Wide) -> [u8; 32] {
+    let mut value = *value;
+    for limb in value.iter_mut() {
+        let (next, borrow) = limb.overflowing_sub(1);
+        *limb = next;
+        if !borrow {
+            break;
+        }
+    }
+    wide_to_bytes(&value)
+}
+
+fn wide_to_bytes(value: &Wide) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    for (i, limb) in value[..4].iter().enumerate() {
+        bytes[24 - i * 8..32 - i * 8].copy_from_slice(&limb.to_be_bytes());
+    }
+    bytes
+}
+
+/// `Some(n)` if `prefix` is `n` ones, which matches iff the key starts with
+/// `n` zero bytes.
+fn leading_zero_prefix_len(prefix: &str) -> Option<usize> {
+    (prefix.len() <= 32 && prefix.bytes().all(|b| b == b'1')).then_some(prefix.len())
+}
+
+/// Rejects a prefix no public key can encode to, and warns when its literal
+/// `head` only fits keys with one particular first byte and is at least
+/// `RARE_PREFIX_RATIO` times rarer than `estimate_attempts` would suggest.
+///
+/// Base58 addresses aren't uniform in their first character: most keys
+/// encode to 44 characters, and those can only start with `2` to `J`.
+fn check_leading_bytes(
+    prefix: &str,
+    head: &str,
+    ranges: &[PubkeyRange],
+) -> Result<Option<String>, VanityError> {
+    if ranges.is_empty() {
+        return Err(VanityError::new(
+            "invalid_criteria",
+            format!("prefix {:?} can never begin a Solana address", prefix),
+        ));
+    }
+
+    let first_byte = ranges[0].0[0];
+    if !ranges.iter().all(|(low, high)| low[0] == first_byte && high[0] == first_byte) {
+        return Ok(None);
+    }
+    let share: f64 = ranges.iter().map(range_share).sum();
+    let ratio = 1.0 / (share * estimate_attempts(head).naive);
+    Ok((ratio >= RARE_PREFIX_RATIO).then(|| {
+        format!(
+            "prefix {:?} only fits keys whose first byte is {:#04x}, so it is about {:.0}x \
+             rarer than its length suggests",
+            prefix, first_byte, ratio
+        )
+    }))
+}
+
+/// Fraction of all public keys whose address starts with the literal `prefix`.
+fn prefix_share(prefix: &str) -> f64 {
+    prefix_pubkey_ranges(prefix).iter().map(range_share).sum()
+}
+
+/// Fraction of all public keys that fall inside `range`.
+fn range_share((low, high): &PubkeyRange) -> f64 {
+    let mut width = [0u8; 32];
+    let mut borrow = false;
+    for i in (0..32).rev() {
+        let (byte, under) = high[i].overflowing_sub(low[i]);
+        let (byte, under_borrow) = byte.overflowing_sub(borrow as u8);
+        width[i] = byte;
+        borrow = under || under_borrow;
+    }
+    let width = width.iter().fold(0.0, |acc, &byte| acc * 256.0 + byte as f64);
+    (width + 1.0) / 2_f64.powi(256)
+}
+
+/// Public key ranges whose base58 encoding starts with `prefix`.
+///
+/// Base58 encodes each leading zero byte as a `1`, then the key's integer
+/// value. So a prefix of `z` ones followed by digits `R` matches a key with
+/// exactly `z` leading zero bytes whose value, written with `L` digits, starts
+/// with `R`: one interval `[R * 58^(L-r), (R + 1) * 58^(L-r))` per length `L`.
+fn prefix_pubkey_ranges(prefix: &str) -> Vec<PubkeyRange> {
+    let leading_ones = prefix.bytes().take_while(|&b| b == b'1').count();
+    if leading_ones >= 32 {
+        return Vec::new();
+    }
+
+    let upper = wide_pow2(8 * (32 - leading_ones));
+    let rest = &prefix.as_bytes()[leading_ones..];
+    if rest.is_empty() {
+        return vec![([0u8; 32], wide_pred_to_bytes(&upper))];
+    }
+
+    // Exactly `leading_ones` zero bytes: the next byte must be non-zero.
+    let lower = wide_pow2(8 * (31 - leading_ones));
+    let mut digits = [0u64; 5];
+    for &c in rest {
+        let digit = BASE58_ALPHABET.iter().position(|&a| a == c).unwrap_or(0);
+        wide_mul_add(&mut digits, 58, digit as u64);
+    }
+    let mut digits_end = digits;
+    wide_mul_add(&mut digits_end, 1, 1);
+
+    let mut ranges = Vec::new();
+    for _ in rest.len()..=MAX_ADDRESS_LEN {
+        if wide_cmp(&digits, &upper).is_ge() {
+            break;
+        }
+
+        let low = std::cmp::max_by(digits, lower, wide_cmp);
+        let high = std::cmp::min_by(digits_end, upper, wide_cmp);
+        if wide_cmp(&low, &high).is_lt() {
+            ranges.push((wide_to_bytes(&low), wide_pred_to_bytes(&high)));
+        }
+
+        wide_mul_add(&mut digits, 58, 0);
+        wide_mul_add(&mut digits_end, 58, 0);
+    }
+    ranges
+}
+
+/// Expected number of candidates needed to hit a prefix, two ways.
+#[derive(Clone, Copy, Debug)]
+pub struct AttemptEstimate {
+    /// Treating each character as its own event: roughly 1-in-58, except
+    /// that leading `1`s encode leading zero bytes of the public key and
+    /// cost 1-in-256 each. A class of `k` characters is a `k`-in-58 event,
+    /// so `?` wildcards are free.
+    pub naive: f64,
+    /// Accounting for how addresses actually start. Most keys encode to 44
+    /// characters, and those can only begin with `2` to `J`, `J` only for the
+    /// largest keys. So `2` to `H` are about 3 times more common than `naive`
+    /// assumes and most other first characters 17 times rarer. This takes
+    /// the exact share of keys whose address starts with the prefix's literal
+    /// head (or with any member of a leading class) and treats the characters
+    /// after it as `naive` does.
+    pub corrected: f64,
+}
+
+/// Estimates the candidates a search for `prefix` needs; see
+/// [`AttemptEstimate`]. A prefix that doesn't parse never matches.
+pub fn estimate_attempts(prefix: &str) -> AttemptEstimate {
+    let Ok(slots) = parse_prefix(prefix) else {
+        return AttemptEstimate {
+            naive: f64::INFINITY,
+            corrected: f64::INFINITY,
+        };
+    };
+    AttemptEstimate {
+        naive: naive_attempts(&slots),
+        corrected: corrected_attempts(&slots),
+    }
+}
+
+fn naive_attempts(slots: &[CharSet]) -> f64 {
+    let one = base58_char_set('1').expect("1 is a base58 character");
+    let leading_ones = slots.iter().take_while(|&&slot| slot == one).count();
+    uniform_attempts(256_f64.powi(leading_ones as i32), &slots[leading_ones..])
+}
+
+fn corrected_attempts(slots: &[CharSet]) -> f64 {
+    let head = literal_head(slots);
+    let (share, rest) = match slots.first() {
+        None => return 1.0,
+        Some(_) if !head.is_empty() => (prefix_share(&head), &slots[head.len()..]),
+        Some(&class) => {
+            let share = BASE58_ALPHABET
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| class & (1 << i) != 0)
+                .map(|(_, &c)| prefix_share(&(c as char).to_string()))
+                .sum();
+            (share, &slots[1..])
+        }
+    };
+    uniform_attempts(1.0 / share, rest)
+}
+
+/// `expected` scaled by each of `slots` as a `k`-in-58 event.
+fn uniform_attempts(expected: f64, slots: &[CharSet]) -> f64 {
+    slots
+        .iter()
+        .fold(expected, |expected, slot| expected * 58.0 / slot.count_ones() as f64)
+}
+
+/// How unusual `address` looks, for ranking finds; higher is rarer.
+///
+/// The score is the sum of three terms, all computed from the address alone:
+///
+/// - **Repeats:** `10 * (r - 1)^2` for the longest run of `r` identical
+///   characters, so a lone character scores 0 and a run of 5 scores 160.
+/// - **Digits:** `5 * |d - e|`, where `d` is the number of digits `1`-`9`
+///   and `e = round(len * 9 / 58)` is the count expected from 9 digits in a
+///   58-character alphabet. All-letter and digit-heavy addresses both score.
+/// - **Leading character:** `round(4 * log2(1 / p))`, where `p` is the share
+///   of all public keys whose address starts with the same character. That
+///   is 16 for `2` to `H`, 32 for `1` and 40 for `K` to `z`.
+pub fn score_address(address: &str) -> u32 {
+    let bytes = address.as_bytes();
+    let longest_run = bytes
+        .chunk_by(|a, b| a == b)
+        .map(<[u8]>::len)
+        .max()
+        .unwrap_or(0) as u32;
+    let repeats = 10 * longest_run.saturating_sub(1).pow(2);
+
+    let digits = bytes.iter().filter(|b| b.is_ascii_digit()).count() as u32;
+    let expected_digits = (bytes.len() as u32 * 9 + 29) / 58;
+    let digit_balance = 5 * digits.abs_diff(expected_digits);
+
+    let leading = bytes
+        .first()
+        .map(|&first| {
+            let share = prefix_share(&(first as char).to_string());
+            if share > 0.0 {
+                (4.0 * (1.0 / share).log2()).round() as u32
+            } else {
+                0
+            }
+        })
+        .unwrap_or(0);
+
+    repeats + digit_balance + leading
+}
+
+/// SLIP-0010 ed25519 derivation; every index is hardened, as ed25519 requires.
+fn slip10_derive_ed25519(seed: &[u8], path: &[u32]) -> Zeroizing<[u8; 32]> {
+    let mut mac = HmacSha512::new_from_slice(b"ed25519 seed").expect("HMAC accepts any key length");
+    mac.update(seed);
+    let mut node = Zeroizing::new([0u8; 64]);
+    node.copy_from_slice(&mac.finalize().into_bytes());
+
+    for &index in path {
+        let mut mac = HmacSha512::new_from_slice(&node[32..]).expect("HMAC accepts any key length");
+        mac.update(&[0]);
+        mac.update(&node[..32]);
+        mac.update(&(index | HARDENED_OFFSET).to_be_bytes());
+        node.copy_from_slice(&mac.finalize().into_bytes());
+    }
+
+    let mut secret = Zeroizing::new([0u8; 32]);
+    secret.copy_from_slice(&node[..32]);
+    secret
+}
+
+/// Parses a BIP44-style path such as `m/44'/501'/0'/0'` into SLIP-0010 indices.
+///
+/// ed25519 only supports hardened derivation, so every segment must carry a
+/// `'` (or `h`) marker.
+fn parse_derivation_path(path: &str) -> Result<Vec<u32>, String> {
+    let mut segments = path.trim().split('/');
+    if segments.next() != Some("m") {
+        return Err(format!("derivation path {:?} must start with \"m/\"", path));
+    }
+
+    let mut indices = Vec::new();
+    for segment in segments {
+        let index = segment
+            .strip_suffix('\'')
+            .or_else(|| segment.strip_suffix('h'))
+            .ok_or_else(|| format!("derivation path segment {:?} must be hardened", segment))?;
+        let index: u32 = index
+            .parse()
+            .ok()
+            .filter(|&i| i < HARDENED_OFFSET)
+            .ok_or_else(|| format!("invalid derivation path segment {:?}", segment))?;
+        indices.push(index);
+    }
+
+    if indices.is_empty() {
+        return Err(format!("derivation path {:?} has no segments", path));
+    }
+
+    Ok(indices)
+}
+
+/// Whether a thread that has made `thread_attempts` attempts should reseed
+/// before its next one: every `interval` attempts, but not before the first.
+fn reseed_due(thread_attempts: u64, interval: u64) -> bool {
+    thread_attempts > 0 && thread_attempts.is_multiple_of(interval)
+}
+
+/// How a worker gets its RNG: from the job's kind and seed and its thread id.
+type RngSource = fn(RngKind, Option<&[u8; 32]>, usize) -> Result<Box<dyn RngCore>, rand::Error>;
+
+/// Per-thread RNG: seeded runs mix the thread id into the job seed so each
+/// thread walks a distinct but reproducible stream.
+fn thread_rng(
+    kind: RngKind,
+    seed: Option<&[u8; 32]>,
+    tid: usize,
+) -> Result<Box<dyn RngCore>, rand::Error> {
+    let thread_seed = seed.map(|seed| {
+        let mut thread_seed = *seed;
+        for (byte, tid_byte) in thread_seed.iter_mut().zip((tid as u64).to_le_bytes()) {
+            *byte ^= tid_byte;
+        }
+        thread_seed
+    });
+    kind.build(thread_seed)
+}
+
+/// Builds a 12-word mnemonic from `entropy` and derives its signing key along `path`.
+fn derive_mnemonic_key(entropy: &[u8; 16], path: &[u32]) -> (Mnemonic, SigningKey) {
+    let mnemonic = Mnemonic::from_entropy(entropy).expect("16 bytes is valid BIP39 entropy");
+    let seed = Zeroizing::new(mnemonic.to_seed(""));
+    let secret = slip10_derive_ed25519(&seed[..], path);
+    (mnemonic, SigningKey::from_bytes(&secret))
+}
+
+/// Decodes `private_key` the way a wallet would and checks that it really is
+/// the keypair for `address` and can sign.
+fn verify_keypair(key_format: KeyFormat, private_key: &str, address: &str) -> Result<(), String> {
+    let keypair_bytes = key_format
+        .decode(private_key)
+        .ok_or_else(|| format!("private key for {} does not decode", address))?;
+    let signing_key = SigningKey::from_keypair_bytes(&keypair_bytes)
+        .map_err(|_| format!("private key for {} does not match its public half", address))?;
+    let verifying_key = signing_key.verifying_key();
+    if fd_bs58::encode_32(verifying_key.as_bytes()) != address {
+        return Err(format!("private key does not belong to {}", address));
+    }
+
+    let signature = signing_key.sign(SELF_TEST_MESSAGE);
+    verifying_key
+        .verify(SELF_TEST_MESSAGE, &signature)
+        .map_err(|_| format!("keypair for {} failed a test signature", address))
+}
+
+fn encode_private_key(secret: &[u8; 32], public: &[u8; 32]) -> String {
+    let mut keypair_bytes = Zeroizing::new([0u8; 64]);
+    keypair_bytes[..32].copy_from_slice(secret);
+    keypair_bytes[32..].copy_from_slice(public);
+    fd_bs58::encode_64(&keypair_bytes[..])
+}
+
+fn encode_keypair_json(secret: &[u8; 32], public: &[u8; 32]) -> String {
+    let keypair_bytes: Zeroizing<Vec<u8>> =
+        Zeroizing::new(secret.iter().chain(public.iter()).copied().collect());
+    serde_json::to_string(&*keypair_bytes).unwrap_or_default()
+}
+
+/// Worker threads for a `threads` request: one per CPU by default, at most
+/// twice that.
+pub fn resolve_thread_count(requested: Option<usize>) -> usize {
+    let default_threads = num_cpus::get();
+    requested.map_or(default_threads, |t| t.clamp(1, default_threads * 2))
+}
+
+/// CPUs that pool threads get pinned to, in order: one per physical core,
+/// then the hyperthread siblings. Worked out and logged once per process.
+fn pin_layout() -> &'static [usize] {
+    static LAYOUT: OnceLock<Vec<usize>> = OnceLock::new();
+    LAYOUT.get_or_init(|| {
+        let layout = core_layout();
+        tracing::info!(?layout, "worker pinning layout");
+        layout
+    })
+}
+
+/// Workers pinned to each CPU of [`pin_layout`], by position, across every
+/// job in the process.
+static PINNED_WORKERS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// Takes the least-used of `slots` positions in `claims`, the earliest on a
+/// tie, so CPUs fill in layout order and only double up once all are taken.
+fn claim_slot(claims: &mut Vec<usize>, slots: usize) -> usize {
+    claims.resize(slots, 0);
+    let (slot, _) = claims
+        .iter()
+        .enumerate()
+        .min_by_key(|&(slot, &count)| (count, slot))
+        .expect("at least one slot");
+    claims[slot] += 1;
+    slot
+}
+
+/// A worker's claim on a CPU: frees the CPU for other jobs and restores the
+/// thread's affinity when dropped.
+struct PinnedWorker {
+    slot: usize,
+    _affinity: AffinityGuard,
+}
+
+impl Drop for PinnedWorker {
+    fn drop(&mut self) {
+        PINNED_WORKERS.lock().unwrap_or_else(PoisonError::into_inner)[self.slot] -= 1;
+    }
+}
+
+/// Pins the calling worker to the free CPU that comes first in
+/// [`pin_layout`] until the returned guard drops. Claims are shared by every
+/// job, so concurrent pinned jobs spread over the cores instead of stacking.
+fn pin_worker(tid: usize) -> Option<PinnedWorker> {
+    let layout = pin_layout();
+    if layout.is_empty() {
+        tracing::warn!(tid, "thread pinning is not supported here");
+        return None;
+    }
+    let mut claims = PINNED_WORKERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let slot = claim_slot(&mut claims, layout.len());
+    let cpu = layout[slot];
+    match pin_to_cpu(cpu) {
+        Some(affinity) => {
+            tracing::debug!(tid, slot, cpu, "pinned worker");
+            Some(PinnedWorker {
+                slot,
+                _affinity: affinity,
+            })
+        }
+        None => {
+            let err = std::io::Error::last_os_error();
+            claims[slot] -= 1;
+            tracing::warn!(tid, cpu, %err, "could not pin worker");
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn core_layout() -> Vec<usize> {
+    // SAFETY: `cpu_set_t` is a plain bitmask, valid when zeroed, and the
+    // kernel is told its exact size.
+    let allowed: Vec<usize> = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect()
+    };
+    let topology = |cpu: usize, file: &str| {
+        fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/topology/{}", cpu, file))
+            .ok()
+            .and_then(|id| id.trim().parse::<u32>().ok())
+    };
+
+    // Hyperthreads share a package and core id. Ranking each CPU by how many
+    // of its siblings come first gives every core a thread before any gets
+    // two; a CPU with no topology counts as a core of its own.
+    let mut siblings = HashMap::new();
+    let mut ranked: Vec<(usize, usize)> = allowed
+        .into_iter()
+        .map(|cpu| {
+            let core = topology(cpu, "physical_package_id").zip(topology(cpu, "core_id"));
+            let rank = core.map_or(0, |core| {
+                let seen = siblings.entry(core).or_insert(0);
+                *seen += 1;
+                *seen - 1
+            });
+            (rank, cpu)
+        })
+        .collect();
+    ranked.sort_unstable();
+    ranked.into_iter().map(|(_, cpu)| cpu).collect()
+}
+
+/// The affinity a pool thread had before [`pin_to_cpu`], put back on drop so
+/// the next job on the thread starts unpinned.
+#[cfg(target_os = "linux")]
+struct AffinityGuard(libc::cpu_set_t);
+
+#[cfg(target_os = "linux")]
+impl Drop for AffinityGuard {
+    fn drop(&mut self) {
+        // SAFETY: as in `core_layout`.
+        let restored = unsafe {
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &self.0) == 0
+        };
+        if !restored {
+            tracing::warn!(err = %std::io::Error::last_os_error(), "could not unpin worker");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_cpu(cpu: usize) -> Option<AffinityGuard> {
+    // SAFETY: as in `core_layout`; `cpu` came from the allowed set, so it is
+    // below `CPU_SETSIZE`.
+    unsafe {
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        let mut original: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut original) != 0 {
+            return None;
+        }
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        (libc::sched_setaffinity(0, size, &set) == 0).then_some(AffinityGuard(original))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn core_layout() -> Vec<usize> {
+    Vec::new()
+}
+
+#[cfg(not(target_os = "linux"))]
+enum AffinityGuard {}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_cpu(_cpu: usize) -> Option<AffinityGuard> {
+    None
+}
+
+/// Counts a worker out when it returns, including by panicking.
+struct WorkerGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Searches for keys matching `criteria`, blocking until the search ends.
+///
+/// Rare hits are collected next to the matches rather than written to disk,
+/// and a rare rule that can't be used fails the call instead of being skipped.
+pub fn find_vanity(criteria: &VanityCriteria, opts: &GenOptions) -> VanityResult {
+    let context = JobContext::new(criteria, opts)?;
+    if let Some(err) = context.skipped_patterns().first() {
+        return Err(err.clone());
+    }
+
+    let matches = Mutex::new(Vec::new());
+    let rare = Mutex::new(Vec::new());
+    let on_event = |event: SearchEvent| match event {
+        SearchEvent::Found(found) => {
+            matches.lock().unwrap_or_else(PoisonError::into_inner).push(found)
+        }
+        SearchEvent::Rare(hit) => rare.lock().unwrap_or_else(PoisonError::into_inner).push(hit),
+        SearchEvent::Error(_) => {}
+    };
+    let summary = run_search(&context, &AtomicBool::new(false), &on_event, &mut |_| {})?;
+
+    Ok(VanityOutcome {
+        matches: matches.into_inner().unwrap_or_else(PoisonError::into_inner),
+        rare: rare.into_inner().unwrap_or_else(PoisonError::into_inner),
+        summary,
+    })
+}
+
+/// Like [`find_vanity`], but hands each match to `on_match` as it is found
+/// instead of collecting them. Returning `ControlFlow::Break` stops the
+/// search, which then ends as `Cancelled`.
+///
+/// `on_match` runs on the worker threads, one call at a time.
+pub fn find_vanity_with<F>(
+    criteria: &VanityCriteria,
+    opts: &GenOptions,
+    on_match: F,
+) -> Result<SearchSummary, VanityError>
+where
+    F: FnMut(&VanityMatch) -> ControlFlow<()> + Send,
+{
+    let context = JobContext::new(criteria, opts)?;
+    if let Some(err) = context.skipped_patterns().first() {
+        return Err(err.clone());
+    }
+
+    let on_match = Mutex::new(on_match);
+    let stop = AtomicBool::new(false);
+    let on_event = |event: SearchEvent| match event {
+        SearchEvent::Found(found) => {
+            let mut on_match = on_match.lock().unwrap_or_else(PoisonError::into_inner);
+            // Workers can still be finishing a match after a `Break`.
+            if !stop.load(Ordering::Relaxed) && on_match(&found).is_break() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+        SearchEvent::Rare(_) | SearchEvent::Error(_) => {}
+    };
+    run_search(&context, &stop, &on_event, &mut |_| {})
+}
+
+/// A search running in the background that yields its matches as they are
+/// found, so callers can `.take(n)` or stop on their own terms.
+///
+/// `GenOptions::count` is ignored: the search runs until the iterator is
+/// dropped or attempts or time run out. Rare hits are not reported. Dropping
+/// the iterator cancels the search and waits for its workers.
+pub struct VanitySearch {
+    matches: Receiver<VanityMatch>,
+    cancel: Arc<AtomicBool>,
+    supervisor: Option<JoinHandle<Result<SearchSummary, VanityError>>>,
+}
+
+impl VanitySearch {
+    /// Validates the search and starts it on a background thread.
+    pub fn new(criteria: &VanityCriteria, opts: &GenOptions) -> Result<Self, VanityError> {
+        let mut context = JobContext::new(criteria, opts)?;
+        if let Some(err) = context.skipped_patterns().first() {
+            return Err(err.clone());
+        }
+        context.target_matches = u64::MAX;
+
+        let (sender, matches) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel_clone = Arc::clone(&cancel);
+        let supervisor = thread::spawn(move || {
+            let on_event = |event: SearchEvent| {
+                if let SearchEvent::Found(found) = event {
+                    let _ = sender.send(found);
+                }
+            };
+            run_search(&context, &cancel_clone, &on_event, &mut |_| {})
+        });
+
+        Ok(Self {
+            matches,
+            cancel,
+            supervisor: Some(supervisor),
+        })
+    }
+
+    /// Stops the search if it is still running and reports how it ended.
+    pub fn finish(mut self) -> Result<SearchSummary, VanityError> {
+        self.cancel.store(true, Ordering::Relaxed);
+        let supervisor = self.supervisor.take().expect("search joined twice");
+        supervisor.join().unwrap_or_else(|_| {
+            Err(VanityError::new(
+                "worker_panicked",
+                "the search thread panicked",
+            ))
+        })
+    }
+}
+
+impl Iterator for VanitySearch {
+    type Item = VanityMatch;
+
+    fn next(&mut self) -> Option<VanityMatch> {
+        self.matches.recv().ok()
+    }
+}
+
+impl Drop for VanitySearch {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
+        }
+    }
+}
+
+/// Async form of [`VanitySearch`] for tokio services: grinds on tokio's
+/// blocking pool and streams matches as they are found.
+///
+/// Validation errors are returned up front. Like `VanitySearch`, the stream
+/// ignores `GenOptions::count` and runs until it is dropped.
+#[cfg(feature = "tokio")]
+pub async fn find_vanity_async(
+    criteria: VanityCriteria,
+    opts: GenOptions,
+) -> Result<impl tokio_stream::Stream<Item = VanityMatch>, VanityError> {
+    let mut context = JobContext::new(&criteria, &opts)?;
+    if let Some(err) = context.skipped_patterns().first() {
+        return Err(err.clone());
+    }
+    context.target_matches = u64::MAX;
+
+    let (sender, matches) = tokio::sync::mpsc::unbounded_channel();
+    tokio::task::spawn_blocking(move || {
+        let stop = AtomicBool::new(false);
+        let on_event = |event: SearchEvent| {
+            if let SearchEvent::Found(found) = event {
+                let _ = sender.send(found);
+            }
+        };
+        let mut on_tick = |_: &SearchStats| {
+            if sender.is_closed() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        };
+        let _ = run_search(&context, &stop, &on_event, &mut on_tick);
+    });
+
+    Ok(tokio_stream::wrappers::UnboundedReceiverStream::new(matches))
+}
+
+/// Runs `context` on its own thread pool until it has all its matches, runs
+/// out of attempts or time, or `cancel` or its `GenOptions::cancel` is set.
+///
+/// Workers report through `on_event` as they go; meanwhile the calling thread
+/// supervises them and passes the live counters to `on_tick` every 50ms.
+///
+/// With `GenOptions::adaptive` set, the search starts with every worker and
+/// every two seconds compares the keys per second per running worker with
+/// the best it has seen. Other processes competing for the CPUs slow every
+/// worker down, so below 80% of the best one more worker is parked, and back
+/// above 95% one is woken. At least one worker always runs. While the
+/// machine stays busy this can settle into parking and waking the same worker
+/// in turn.
+pub fn run_search(
+    context: &JobContext,
+    cancel: &AtomicBool,
+    on_event: &(dyn Fn(SearchEvent) + Sync),
+    on_tick: &mut dyn FnMut(&SearchStats),
+) -> Result<SearchSummary, VanityError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(context.num_threads)
+        .build()
+        .map_err(|err| {
+            VanityError::new(
+                "thread_pool_failed",
+                format!("could not start worker threads: {}", err),
+            )
+        })?;
+    run_search_in(&pool, context, cancel, on_event, on_tick)
+}
+
+/// Like [`run_search`], but runs the workers on `pool` so a long-lived
+/// caller can reuse the same threads for every job.
+///
+/// Each job takes `context.threads()` of the pool's threads; workers beyond
+/// what is free wait in the pool's queue until another job finishes.
+pub fn run_search_in(
+    pool: &rayon::ThreadPool,
+    context: &JobContext,
+    cancel: &AtomicBool,
+    on_event: &(dyn Fn(SearchEvent) + Sync),
+    on_tick: &mut dyn FnMut(&SearchStats),
+) -> Result<SearchSummary, VanityError> {
+    let job_start = Instant::now();
+    let num_threads = context.num_threads;
+    let stop_flag = AtomicBool::new(false);
+    let stats = SearchStats::new(num_threads);
+    let active_workers = AtomicUsize::new(num_threads);
+    // The first worker that can't go on stops the whole search.
+    let worker_error = Mutex::new(None);
+    let search_span = tracing::info_span!("search", threads = num_threads);
+    let _entered = search_span.enter();
+    tracing::debug!(
+        max_attempts = ?context.max_attempts,
+        target = context.target_matches,
+        "search started"
+    );
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.in_place_scope(|scope| {
+            for tid in 0..num_threads {
+                let stop_flag = &stop_flag;
+                let stats = &stats;
+                let active_workers = &active_workers;
+                let search_span = &search_span;
+                let worker_error = &worker_error;
+
+                scope.spawn(move |_| {
+                    let _guard = WorkerGuard(active_workers);
+                    let _entered =
+                        tracing::debug_span!(parent: search_span, "worker", tid).entered();
+                    let result =
+                        generate_vanity(tid, context, job_start, stop_flag, stats, on_event);
+                    if let Err(err) = result {
+                        stop_flag.store(true, Ordering::Relaxed);
+                        worker_error
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .get_or_insert(err);
+                    }
+                });
+            }
+
+            let mut timed_out = false;
+            let mut cancelled = false;
+            let mut scaler = context.adaptive.then(|| ThreadScaler::new(&stats));
+
+            loop {
+                thread::sleep(TICK_INTERVAL);
+
+                if let Some(scaler) = &mut scaler {
+                    scaler.adjust(&stats);
+                }
+
+                if !cancelled
+                    && (cancel.load(Ordering::Relaxed) || context.cancel_token.is_cancelled())
+                {
+                    cancelled = true;
+                    stop_flag.store(true, Ordering::Relaxed);
+                }
+
+                on_tick(&stats);
+
+                if !timed_out && context.timeout.is_some_and(|t| job_start.elapsed() >= t) {
+                    timed_out = true;
+                    stop_flag.store(true, Ordering::Relaxed);
+                }
+
+                if active_workers.load(Ordering::Relaxed) == 0 {
+                    break;
+                }
+            }
+
+            stop_flag.store(true, Ordering::Relaxed);
+            (timed_out, cancelled)
+        })
+    }));
+
+    let (timed_out, cancelled) = outcome.map_err(|_| {
+        tracing::error!("a worker panicked");
+        VanityError::new(
+            "worker_panicked",
+            "a worker thread panicked; the job was stopped",
+        )
+    })?;
+    if let Some(err) = worker_error.into_inner().unwrap_or_else(PoisonError::into_inner) {
+        return Err(err);
+    }
+
+    let elapsed = job_start.elapsed();
+    // Workers bump the counters before checking the limits, so clamp them.
+    let attempts = stats.attempts();
+    let attempts = context
+        .max_attempts
+        .map_or(attempts, |max| attempts.min(max));
+    let matches = stats.matches.load(Ordering::Relaxed);
+    let matches = if context.keep_going {
+        matches
+    } else {
+        matches.min(context.target_matches)
+    };
+    let reason = if cancelled {
+        StopReason::Cancelled
+    } else if !context.keep_going && matches == context.target_matches {
+        StopReason::Found
+    } else if timed_out {
+        StopReason::Timeout
+    } else if context.max_attempts.is_some_and(|max| attempts >= max) {
+        StopReason::Exhausted
+    } else {
+        StopReason::Failed
+    };
+    tracing::info!(
+        ?reason,
+        attempts,
+        matches,
+        elapsed_ms = elapsed.as_millis() as u64,
+        "search finished"
+    );
+
+    Ok(SearchSummary {
+        reason,
+        attempts,
+        matches,
+        elapsed,
+    })
+}
+
+/// Adaptive mode's bookkeeping; see [`run_search`] for the heuristic.
+struct ThreadScaler {
+    last_check: Instant,
+    last_attempts: u64,
+    best_rate: f64,
+}
+
+impl ThreadScaler {
+    fn new(stats: &SearchStats) -> Self {
+        Self {
+            last_check: Instant::now(),
+            last_attempts: stats.attempts(),
+            best_rate: 0.0,
+        }
+    }
+
+    fn adjust(&mut self, stats: &SearchStats) {
+        let elapsed = self.last_check.elapsed();
+        if elapsed < ADAPT_INTERVAL {
+            return;
+        }
+        let attempts = stats.attempts();
+        let active = stats.active_threads();
+        let rate = (attempts - self.last_attempts) as f64 / elapsed.as_secs_f64() / active as f64;
+        self.last_check = Instant::now();
+        self.last_attempts = attempts;
+        self.best_rate = self.best_rate.max(rate);
+
+        let target = if rate < self.best_rate * SLOWDOWN_RATIO {
+            active.saturating_sub(1).max(1)
+        } else if rate > self.best_rate * RECOVERED_RATIO {
+            (active + 1).min(stats.threads.len())
+        } else {
+            active
+        };
+        if target != active {
+            tracing::debug!(active = target, rate, best_rate = self.best_rate, "rescaled workers");
+            stats.active_threads.store(target, Ordering::Relaxed);
+        }
+    }
+}
+
+fn generate_vanity(
+    tid: usize,
+    job_context: &JobContext,
+    job_start: Instant,
+    stop_flag: &AtomicBool,
+    stats: &SearchStats,
+    on_event: &(dyn Fn(SearchEvent) + Sync),
+) -> Result<(), VanityError> {
+    let seed_failed = |err: rand::Error| {
+        tracing::warn!(%err, "could not seed the RNG");
+        VanityError::new(
+            "rng_seed_failed",
+            format!("thread {} could not seed its RNG: {}", tid, err),
+        )
+    };
+    let _pinned = job_context.pin_threads.then(|| pin_worker(tid)).flatten();
+    let mut rng = (job_context.rng_source)(job_context.rng, job_context.seed.as_ref(), tid)
+        .map_err(seed_failed)?;
+    let mut secret_bytes = Zeroizing::new([0u8; 32]);
+    let mut mnemonic_entropy = Zeroizing::new([0u8; 16]);
+    let mut address_buf = [0u8; MAX_ADDRESS_LEN];
+    let thread_counter = &stats.threads[tid].0;
+    let mut thread_local_attempts = 0u64;
+
+    while !stop_flag.load(Ordering::Relaxed) {
+        if tid >= stats.active_threads() {
+            thread::sleep(PARK_INTERVAL);
+            continue;
+        }
+        if job_context
+            .reseed_interval
+            .is_some_and(|interval| reseed_due(thread_local_attempts, interval))
+        {
+            tracing::debug!(tid, thread_local_attempts, "reseeding the RNG");
+            rng = (job_context.rng_source)(job_context.rng, None, tid).map_err(seed_failed)?;
+        }
+
+        // Mnemonic mode pays for PBKDF2 on every candidate, so it grinds far slower.
+        let (signing_key, mnemonic) = if let Some(path) = &job_context.derivation_path {
+            rand::RngCore::fill_bytes(&mut rng, &mut mnemonic_entropy[..]);
+            let (mnemonic, signing_key) = derive_mnemonic_key(&mnemonic_entropy, path);
+            (signing_key, Some(mnemonic))
+        } else {
+            rand::RngCore::fill_bytes(&mut rng, &mut secret_bytes[..]);
+            (SigningKey::from_bytes(&secret_bytes), None)
+        };
+        
+        let public_key = signing_key.verifying_key();
+        let public_key_bytes = public_key.as_bytes();
+        let attempts = stats.attempts.fetch_add(1, Ordering::Relaxed) + 1;
+        thread_local_attempts += 1;
+        thread_counter.store(thread_local_attempts, Ordering::Relaxed);
+        if job_context
+            .max_attempts
+            .is_some_and(|max| attempts > max)
+        {
+            stop_flag.store(true, Ordering::Relaxed);
+            break;
+        }
+
+        if job_context.rejects_public_key(public_key_bytes) {
+            continue;
+        }
+
+        // Into a per-thread buffer: a key that matches nothing allocates nothing.
+        let address = encode_address(public_key_bytes, &mut address_buf);
+        let address_bytes = address.as_bytes();
+
+        let rare_match = find_rare_pattern(address_bytes, job_context)
+            .filter(|_| beats_best(job_context, &stats.best_rare_score, address));
+        if let Some(rare_match) = rare_match {
+            let secret_bytes_key = Zeroizing::new(signing_key.to_bytes());
+            let private_key = job_context
+                .key_format
+                .encode(&secret_bytes_key, public_key_bytes);
+
+            on_event(SearchEvent::Rare(RareHit {
+                address: address.to_string(),
+                private_key,
+                pattern: rare_match.pattern,
+                position: rare_match.position,
+                label: rare_match.label,
+                count: rare_match.count,
+                attempts,
+                elapsed_ms: job_start.elapsed().as_millis() as u64,
+                output_file: rare_match.output_file,
+                score: score_address(address),
+            }));
+        }
+
+        if let Some(criteria_match) = job_context.match_address(address_bytes) {
+            let secret_bytes_key = Zeroizing::new(signing_key.to_bytes());
+            let private_key = job_context
+                .key_format
+                .encode(&secret_bytes_key, public_key_bytes);
+            if let Err(message) = verify_keypair(job_context.key_format, &private_key, address) {
+                tracing::error!(%message, "found keypair failed verification");
+                on_event(SearchEvent::Error(VanityError::new(
+                    "keypair_verification_failed",
+                    message,
+                )));
+                continue;
+            }
+
+            if !beats_best(job_context, &stats.best_found_score, address) {
+                continue;
+            }
+            let is_new = stats
+                .found_keys
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(*public_key_bytes);
+            if !is_new {
+                tracing::debug!(%address, "skipped a repeated match");
+                continue;
+            }
+            // Another thread may have already taken the last slot.
+            let match_number = stats.matches.fetch_add(1, Ordering::Relaxed) + 1;
+            if !job_context.keep_going && match_number > job_context.target_matches {
+                break;
+            }
+            tracing::debug!(%address, attempts, "match found");
+
+            on_event(SearchEvent::Found(VanityMatch {
+                score: score_address(address),
+                address: address.to_string(),
+                private_key,
+                signing_key,
+                attempts,
+                elapsed_ms: job_start.elapsed().as_millis() as u64,
+                position: criteria_match.contains_offset,
+                matched_prefix: criteria_match
+                    .matched_prefix
+                    .map(|index| job_context.prefix_string(index)),
+                mnemonic: mnemonic.map(|m| m.to_string()),
+            }));
+
+            if !job_context.keep_going && match_number == job_context.target_matches {
+                stop_flag.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+    tracing::debug!(attempts = thread_local_attempts, "worker stopped");
+    Ok(())
+}
+
+/// Outside keep-going mode, always true. Inside it, true only if `address`
+/// outscores everything recorded in `best`, which it then becomes.
+fn beats_best(job_context: &JobContext, best: &AtomicU32, address: &str) -> bool {
+    if !job_context.keep_going {
+        return true;
+    }
+    let score = score_address(address);
+    best.fetch_max(score, Ordering::Relaxed) < score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RNG whose every read fails, like an OS generator that is gone.
+    struct FailingRng;
+
+    impl RngCore for FailingRng {
+        fn next_u32(&mut self) -> u32 {
+            unreachable!("seeding only calls try_fill_bytes")
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            unreachable!("seeding only calls try_fill_bytes")
+        }
+
+        fn fill_bytes(&mut self, _: &mut [u8]) {
+            unreachable!("seeding only calls try_fill_bytes")
+        }
+
+        fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand::Error> {
+            Err(rand::Error::new(std::io::Error::other("entropy source unavailable")))
+        }
+    }
+
+    fn context(criteria: VanityCriteria) -> JobContext {
+        JobContext::new(&criteria, &GenOptions::default()).expect("valid criteria")
+    }
+
+    #[test]
+    fn every_supplied_criterion_must_match() {
+        let context = context(VanityCriteria {
+            prefixes: vec!["So".to_string()],
+            suffix: Some("11".to_string()),
+            ..Default::default()
+        });
+        assert!(context.match_address(b"SoAbcdEfgHijKmnoPqrStuVwxyz23456789ABCDEFG11").is_some());
+        assert!(context.match_address(b"SoAbcdEfgHijKmnoPqrStuVwxyz23456789ABCDEFG12").is_none());
+        assert!(context.match_address(b"TuAbcdEfgHijKmnoPqrStuVwxyz23456789ABCDEFG11").is_none());
+    }
+
+    #[test]
+    fn prefix_longer_than_an_address_is_an_error() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["A".repeat(MAX_ADDRESS_LEN + 1)],
+            ..Default::default()
+        };
+        let err = JobContext::new(&criteria, &GenOptions::default()).err().expect("rejected");
+        assert_eq!(err.code, "invalid_criteria");
+    }
+
+    #[test]
+    fn prefix_as_long_as_the_largest_address_is_accepted() {
+        let mut buf = [0; MAX_ADDRESS_LEN];
+        let largest = encode_address(&[255; 32], &mut buf);
+        assert_eq!(largest.len(), MAX_ADDRESS_LEN);
+        context(VanityCriteria {
+            prefixes: vec![largest.to_string()],
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn prefixes_tied_to_one_first_byte_get_a_warning() {
+        let warnings = |prefix: &str| {
+            context(VanityCriteria {
+                prefixes: vec![prefix.to_string()],
+                ..Default::default()
+            })
+            .prefix_warnings()
+            .len()
+        };
+        assert_eq!(warnings("abc"), 1);
+        assert_eq!(warnings("zzzzzz"), 1);
+        assert_eq!(warnings("A"), 0);
+    }
+
+    #[test]
+    fn reseeding_happens_on_each_interval_boundary() {
+        let due: Vec<u64> = [0, 1, 99, 100, 101, 199, 200, 201]
+            .into_iter()
+            .filter(|&attempts| reseed_due(attempts, 100))
+            .collect();
+        assert_eq!(due, [100, 200]);
+    }
+
+    #[test]
+    fn workers_rebuild_their_rng_every_interval() {
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+        let criteria = VanityCriteria {
+            prefixes: vec!["zzzzzzzz".to_string()],
+            ..Default::default()
+        };
+        let options = GenOptions {
+            threads: Some(1),
+            max_attempts: Some(1000),
+            reseed_interval: Some(100),
+            ..Default::default()
+        };
+        let mut context = JobContext::new(&criteria, &options).expect("valid criteria");
+        context.rng_source = |kind, seed, tid| {
+            BUILDS.fetch_add(1, Ordering::Relaxed);
+            thread_rng(kind, seed, tid)
+        };
+        let summary = run_search(&context, &AtomicBool::new(false), &|_| {}, &mut |_| {})
+            .expect("search ran");
+        assert_eq!(summary.attempts, 1000);
+        // The first build, then one each time the count reaches 100, 200, ...
+        // 1000, the last just before the worker sees the cap.
+        assert_eq!(BUILDS.load(Ordering::Relaxed), 11);
+    }
+
+    #[test]
+    fn corrected_estimates_match_sampled_addresses() {
+        const SAMPLES: u32 = 200_000;
+        let mut rng = ChaCha20Rng::seed_from_u64(92);
+        let mut buf = [0; MAX_ADDRESS_LEN];
+        let (mut twos, mut zs) = (0u32, 0u32);
+        for _ in 0..SAMPLES {
+            let mut key = [0; 32];
+            rng.fill_bytes(&mut key);
+            match encode_address(&key, &mut buf).as_bytes()[0] {
+                b'2' => twos += 1,
+                b'z' => zs += 1,
+                _ => {}
+            }
+        }
+
+        // Sampling error is about 1% for `2` and 7% for `z`.
+        for (prefix, hits, tolerance) in [("2", twos, 0.05), ("z", zs, 0.25)] {
+            let sampled = f64::from(SAMPLES) / f64::from(hits);
+            let estimated = estimate_attempts(prefix).corrected;
+            assert!(
+                (sampled / estimated - 1.0).abs() < tolerance,
+                "{:?}: sampled {:.1}, estimated {:.1}",
+                prefix,
+                sampled,
+                estimated
+            );
+        }
+    }
+
+    #[test]
+    fn encode_address_matches_fd_bs58() {
+        let mut keys = vec![[0; 32], [255; 32], [1; 32]];
+        for zeros in 1..32 {
+            let mut key = [0; 32];
+            key[zeros..].fill(0x9c);
+            keys.push(key);
+        }
+        let mut rng = ChaCha20Rng::seed_from_u64(95);
+        keys.extend((0..1000).map(|_| {
+            let mut key = [0; 32];
+            rng.fill_bytes(&mut key);
+            key
+        }));
+
+        let mut buf = [0; MAX_ADDRESS_LEN];
+        for key in keys {
+            assert_eq!(encode_address(&key, &mut buf), fd_bs58::encode_32(key), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn pinned_workers_take_free_cpus_first() {
+        let mut claims = Vec::new();
+        let first: Vec<usize> = (0..3).map(|_| claim_slot(&mut claims, 4)).collect();
+        assert_eq!(first, [0, 1, 2]);
+        // A worker from another job finishes, freeing its CPU.
+        claims[1] -= 1;
+        let next: Vec<usize> = (0..3).map(|_| claim_slot(&mut claims, 4)).collect();
+        assert_eq!(next, [1, 3, 0]);
+        assert_eq!(claims, [2, 1, 1, 1]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unpinning_restores_the_original_affinity() {
+        fn affinity() -> Vec<usize> {
+            // SAFETY: as in `core_layout`.
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                assert_eq!(
+                    libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set),
+                    0
+                );
+                (0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect()
+            }
+        }
+
+        // On a thread of its own, so a failure can't leave the runner pinned.
+        thread::spawn(|| {
+            let original = affinity();
+            let cpu = *original.last().unwrap();
+            let guard = pin_to_cpu(cpu).expect("pinned");
+            assert_eq!(affinity(), [cpu]);
+            drop(guard);
+            assert_eq!(affinity(), original);
+        })
+        .join()
+        .unwrap();
+    }
+
+    fn pattern(pattern: &str, min_length: usize) -> PatternConfig {
+        PatternConfig {
+            pattern: pattern.to_string(),
+            min_length,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn disabled_patterns_produce_no_rule() {
+        let patterns = [
+            PatternConfig {
+                enabled: Some(false),
+                ..pattern("A", 4)
+            },
+            pattern("B", 4),
+        ];
+        let mut skipped = Vec::new();
+        let rules = preprocess_patterns(&patterns, &mut skipped).expect("one rule");
+        let labels: Vec<&str> = rules.iter().map(|rule| rule.label.as_str()).collect();
+        assert_eq!(labels, ["B"]);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn zero_min_length_patterns_are_dropped() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["Ab".to_string()],
+            patterns: vec![pattern("A", 0)],
+            ..Default::default()
+        };
+        let context = context(criteria);
+        assert_eq!(context.skipped_patterns().len(), 1);
+        assert_eq!(context.skipped_patterns()[0].code, "invalid_pattern");
+        assert!(context.pattern_rules.is_none());
+        let address = b"AbcdEfgHijKmnoPqrStuVwxyz23456789ABCDEFGHJKL";
+        assert!(find_rare_pattern(address, &context).is_none());
+    }
+
+    #[test]
+    fn empty_prefix_is_an_error() {
+        let criteria = VanityCriteria {
+            prefixes: vec![String::new()],
+            ..Default::default()
+        };
+        let err = JobContext::new(&criteria, &GenOptions::default()).err().expect("rejected");
+        assert_eq!(err.code, "invalid_criteria");
+        assert_eq!(err.message, "prefix must be non-empty");
+    }
+
+    #[test]
+    fn criteria_are_trimmed() {
+        let padded = context(VanityCriteria {
+            prefixes: vec![" SoL ".to_string()],
+            suffix: Some("\txyz\n".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(padded.prefixes(), ["SoL"]);
+        assert!(padded.match_address(b"SoLdEfgHijKmnoPqrStuVwxyz23456789ABCDEFGxyz").is_some());
+
+        let blank = VanityCriteria {
+            prefixes: vec!["  \n".to_string()],
+            ..Default::default()
+        };
+        assert!(JobContext::new(&blank, &GenOptions::default()).is_err());
+    }
+
+    #[test]
+    fn cancel_token_stops_a_search_promptly() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["zzzzzzzz".to_string()],
+            ..Default::default()
+        };
+        let options = GenOptions::default();
+        let cancel = options.cancel.clone();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+
+        let start = Instant::now();
+        let outcome = find_vanity(&criteria, &options).expect("search ran");
+        canceller.join().unwrap();
+        assert_eq!(outcome.summary.reason, StopReason::Cancelled);
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn rng_seeding_failure_stops_the_search_with_an_error() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["zzzzzzzz".to_string()],
+            ..Default::default()
+        };
+        let options = GenOptions {
+            threads: Some(2),
+            ..Default::default()
+        };
+        let mut context = JobContext::new(&criteria, &options).expect("valid criteria");
+        context.rng_source = |_, _, _| Ok(Box::new(ChaCha20Rng::from_rng(FailingRng)?));
+        let result = run_search(&context, &AtomicBool::new(false), &|_| {}, &mut |_| {});
+        let err = result.expect_err("search failed");
+        assert_eq!(err.code, "rng_seed_failed");
+        assert!(err.message.contains("entropy source unavailable"), "{}", err.message);
+    }
+
+    #[test]
+    fn workers_drawing_the_same_keys_report_each_match_once() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["2".to_string()],
+            ..Default::default()
+        };
+        let options = GenOptions {
+            threads: Some(2),
+            count: 4,
+            seed: Some([100; 32]),
+            ..Default::default()
+        };
+        let mut context = JobContext::new(&criteria, &options).expect("valid criteria");
+        // Leaves the thread id out of the seed, so both workers walk one stream.
+        context.rng_source = |kind, seed, _| kind.build(seed.copied());
+        let found = Mutex::new(Vec::new());
+        let on_event = |event| {
+            if let SearchEvent::Found(found_match) = event {
+                found.lock().unwrap().push(found_match.address);
+            }
+        };
+        run_search(&context, &AtomicBool::new(false), &on_event, &mut |_| {})
+            .expect("search ran");
+        let found = found.into_inner().unwrap();
+        let addresses: HashSet<&String> = found.iter().collect();
+        assert_eq!(found.len(), 4);
+        assert_eq!(addresses.len(), 4, "repeated a match");
+    }
+
+    /// Counts heap allocations on threads that set `COUNT_ALLOCATIONS`.
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static COUNT_ALLOCATIONS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+
+    // SAFETY: every call is passed straight on to `System`.
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            if COUNT_ALLOCATIONS.try_with(|count| count.get()).unwrap_or(false) {
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            }
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn non_matching_candidates_do_not_allocate() {
+        let rule = |kind, min_length| PatternConfig {
+            kind,
+            ..pattern("z", min_length)
+        };
+        // A suffix can't be ruled out before encoding, so every candidate is
+        // encoded and checked against each kind of rare rule.
+        let criteria = VanityCriteria {
+            suffix: Some("zzzzzz".to_string()),
+            patterns: vec![
+                pattern("z", 6),
+                pattern("zx", 4),
+                rule(PatternConfigKind::Count, 12),
+                rule(PatternConfigKind::Sequential, 10),
+                rule(PatternConfigKind::Palindrome, 14),
+                PatternConfig {
+                    regex: Some("zzzzzz".to_string()),
+                    ..pattern("", 1)
+                },
+            ],
+            ..Default::default()
+        };
+        let options = GenOptions {
+            threads: Some(2),
+            ..Default::default()
+        };
+        let mut context = JobContext::new(&criteria, &options).expect("valid criteria");
+        // Counting starts once a worker has its RNG.
+        context.rng_source = |kind, seed, tid| {
+            let rng = thread_rng(kind, seed, tid);
+            COUNT_ALLOCATIONS.with(|count| count.set(true));
+            rng
+        };
+
+        let cancel = AtomicBool::new(false);
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let mut window = None;
+        let mut measured = None;
+        let on_tick = &mut |stats: &SearchStats| {
+            let attempts = stats.attempts();
+            let (first_attempts, first_allocations) = *window.get_or_insert_with(|| {
+                (attempts, ALLOCATIONS.load(Ordering::Relaxed))
+            });
+            if attempts >= first_attempts + 2000 || Instant::now() > deadline {
+                let allocations = ALLOCATIONS.load(Ordering::Relaxed) - first_allocations;
+                measured = Some((attempts - first_attempts, allocations));
+                cancel.store(true, Ordering::Relaxed);
+            }
+        };
+        run_search(&context, &cancel, &|_| {}, on_tick).expect("search ran");
+        let (candidates, allocations) = measured.expect("search was measured");
+        assert!(candidates >= 2000, "only {} candidates in time", candidates);
+        assert_eq!(allocations, 0, "allocations over {} candidates", candidates);
+    }
+
+    #[test]
+    fn vanity_search_yields_until_dropped() {
+        let criteria = VanityCriteria {
+            prefixes: vec!["2".to_string()],
+            ..Default::default()
+        };
+        let search = VanitySearch::new(&criteria, &GenOptions::default()).expect("valid search");
+        assert_eq!(search.take(5).count(), 5);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_stream_yields_until_dropped() {
+        use tokio_stream::StreamExt;
+
+        let criteria = VanityCriteria {
+            prefixes: vec!["2".to_string()],
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let found = runtime.block_on(async {
+            let stream = find_vanity_async(criteria, GenOptions::default()).await.unwrap();
+            stream.take(5).collect::<Vec<_>>().await
+        });
+        assert_eq!(found.len(), 5);
+    }
+
+    #[test]
+    fn ones_prefix_needs_as_many_leading_zero_bytes() {
+        assert_eq!(leading_zero_prefix_len("111"), Some(3));
+        assert_eq!(leading_zero_prefix_len("11A"), None);
+
+        let context = context(VanityCriteria {
+            prefixes: vec!["111".to_string()],
+            ..Default::default()
+        });
+        let mut buf = [0u8; MAX_ADDRESS_LEN];
+        for zeros in 0..=4 {
+            let mut key = [0x5au8; 32];
+            key[..zeros].fill(0);
+            let matches = zeros >= 3;
+            assert_eq!(context.rejects_public_key(&key), !matches, "{} zero bytes", zeros);
+            let address = encode_address(&key, &mut buf);
+            assert_eq!(context.match_address(address.as_bytes()).is_some(), matches);
+        }
+    }
+}
@@ -1,26 +1,85 @@
+#[cfg(feature = "metrics")]
+mod metrics;
+mod seal;
+
+use clap::{Parser, ValueEnum};
 use ed25519_dalek::SigningKey;
-use rand::rngs::OsRng;
-use rand_chacha::ChaCha20Rng;
-use rand::SeedableRng;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use seal::KeySealer;
 use serde::{Deserialize, Serialize};
+use vanity_gen::{
+    encode_address, estimate_attempts, resolve_thread_count, run_search_in, usable_patterns,
+    Config, GenOptions, JobContext, KeyFormat, PatternConfig, RareHit, RngKind, SearchEvent,
+    SearchStats, SearchSummary, StopReason, VanityCriteria, VanityError, VanityMatch,
+    MAX_ADDRESS_LEN,
+};
+use zeroize::Zeroizing;
+use tracing_subscriber::EnvFilter;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 #[serde(tag = "type")]
 enum OutputMessage {
+    #[serde(rename = "started")]
+    Started {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+        prefixes: Vec<String>,
+        threads: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expected_attempts: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_attempts: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timeout_ms: Option<u64>,
+        count: u64,
+    },
     #[serde(rename = "progress")]
-    Progress { tid: usize, attempts: u64 },
+    Progress {
+        tid: usize,
+        scope: ProgressScope,
+        attempts: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rate: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_seconds: Option<f64>,
+        /// `attempts` as a percentage of the expected attempts, on `total`
+        /// progress of jobs with an estimate. Finding a key is luck, not
+        /// work completed: about 37% of searches are still going at 100,
+        /// so this can exceed 100 and is capped at `MAX_PROGRESS_PERCENT`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        percent: Option<f64>,
+    },
     #[serde(rename = "found")]
     Found {
         address: String,
         private_key: String,
         attempts: u64,
+        elapsed_ms: u64,
+        /// Byte offset of the `contains` match.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        position: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        matched_prefix: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mnemonic: Option<String>,
+        /// The keypair file written under `outDir`, if one was requested.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        key_file: Option<String>,
+        /// Rarity of `address`; see `vanity_gen::score_address`.
+        score: u32,
     },
     #[serde(rename = "rare")]
     Rare {
@@ -28,335 +87,2477 @@ enum OutputMessage {
         private_key: String,
         pattern: String,
         attempts: u64,
+        elapsed_ms: u64,
+        /// Byte offset where the pattern starts, except for `count` rules.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        position: Option<usize>,
+        /// Which config rule fired; its pattern string unless it was labelled.
+        label: String,
+        /// For `count` rules, how many times the pattern occurred.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        count: Option<usize>,
+        score: u32,
+    },
+    #[serde(rename = "exhausted")]
+    Exhausted { attempts: u64 },
+    #[serde(rename = "timeout")]
+    Timeout { attempts: u64, elapsed_ms: u64 },
+    #[serde(rename = "stopped")]
+    Stopped { attempts: u64 },
+    #[serde(rename = "benchmark")]
+    Benchmark {
+        threads: usize,
+        total: u64,
+        rate: f64,
+    },
+    #[serde(rename = "error")]
+    Error { code: String, message: String },
+    #[serde(rename = "warning")]
+    Warning { message: String },
+    #[serde(rename = "estimate")]
+    Estimate {
+        prefix: String,
+        /// Accounts for how unevenly addresses start; what jobs report.
+        expected_attempts: f64,
+        /// The usual 58-per-character figure, for comparison.
+        naive_expected_attempts: f64,
+    },
+    /// Reply to a `status` request: every job still running.
+    #[serde(rename = "status")]
+    StatusReport { jobs: Vec<JobStatus> },
+    /// Reply to a `list` request: the ids of every job still running.
+    #[serde(rename = "jobs")]
+    JobList { ids: Vec<String> },
+    /// The active rare rules, sent once at startup, in reply to a
+    /// `configInfo` request and after a successful `reloadConfig`. Empty when
+    /// no config was loaded.
+    #[serde(rename = "config")]
+    ConfigLoaded { patterns: Vec<PatternSummary> },
+    /// Heartbeat under `--heartbeat-ms` while no job is running.
+    #[serde(rename = "idle")]
+    Idle {
+        /// Since the last job finished, or since startup.
+        since_ms: u64,
+    },
+    /// Sent once at startup and in reply to a `version` request.
+    #[serde(rename = "version")]
+    Version {
+        version: String,
+        /// Commit the binary was built from, if `VANITY_GEN_GIT_SHA` was set.
+        git_sha: Option<String>,
+        /// Worker threads a job gets when it doesn't ask for a count.
+        threads: usize,
+        /// What this build supports; see `CAPABILITIES`.
+        features: Vec<String>,
+    },
+    /// Last message of every search job, whatever ended it.
+    #[serde(rename = "summary")]
+    Summary {
+        reason: StopReason,
+        attempts: u64,
+        elapsed_ms: u64,
+        rate: f64,
+        matches: u64,
     },
 }
 
-#[derive(Deserialize)]
-struct InputMessage {
-    prefix: Option<String>,
+#[derive(Clone, Serialize)]
+struct JobStatus {
+    #[serde(rename = "jobId")]
+    job_id: String,
+    /// Empty for benchmarks.
+    prefixes: Vec<String>,
+    attempts: u64,
+    /// Average keys per second since the job started.
+    rate: f64,
+    elapsed_ms: u64,
 }
 
-const REPORT_INTERVAL_MS: u64 = 250;
-
-#[derive(Deserialize, Clone)]
-struct PatternConfig {
+/// One usable rare rule from the config, as `ConfigLoaded` lists it.
+#[derive(Clone, Serialize)]
+struct PatternSummary {
+    /// The rule's pattern, regex or word list; see `PatternConfig::name`.
     pattern: String,
-    #[serde(rename = "minLength")]
     min_length: usize,
+    /// What `Rare` messages from this rule carry as their `label`.
+    label: String,
 }
 
-#[derive(Deserialize)]
-struct Config {
-    patterns: Vec<PatternConfig>,
+impl OutputMessage {
+    fn config_loaded(config: Option<&Vec<PatternConfig>>) -> Self {
+        let patterns = usable_patterns(config.map_or(&[], Vec::as_slice))
+            .into_iter()
+            .map(|pattern| PatternSummary {
+                pattern: pattern.name(),
+                min_length: pattern.min_length,
+                label: pattern.label.clone().unwrap_or_else(|| pattern.name()),
+            })
+            .collect();
+        OutputMessage::ConfigLoaded { patterns }
+    }
 }
 
-struct JobContext {
-    prefix_bytes: Vec<u8>,
-    pattern_rules: Option<Vec<PatternRule>>,
+/// Whether a `Progress` message covers the whole job or a single worker.
+///
+/// The aggregate message keeps reporting `tid: 0` so existing consumers that
+/// ignore `scope` still see job-wide totals.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ProgressScope {
+    Total,
+    Thread,
 }
 
-enum PatternKind {
-    Single(u8),
-    Sequence(Vec<u8>),
+impl OutputMessage {
+    fn error(code: &str, message: impl Into<String>) -> Self {
+        OutputMessage::Error {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
 }
 
-struct PatternRule {
-    kind: PatternKind,
-    min_length: usize,
-}
+/// Stands in for secrets on stdout in redacted mode.
+const REDACTED: &str = "<redacted>";
 
-impl JobContext {
-    fn new(prefix: &str, config: Option<&Vec<PatternConfig>>) -> Self {
-        Self {
-            prefix_bytes: prefix.as_bytes().to_vec(),
-            pattern_rules: preprocess_patterns(config),
+impl OutputMessage {
+    /// The message as redacted mode shows it on stdout: no private key or
+    /// mnemonic.
+    fn redacted(self) -> Self {
+        self.map_secrets(|_| REDACTED.to_string())
+    }
+
+    /// Passes the private key and mnemonic, if the message has them, through
+    /// `map`.
+    fn map_secrets(mut self, map: impl Fn(&str) -> String) -> Self {
+        match &mut self {
+            OutputMessage::Found {
+                private_key,
+                mnemonic,
+                ..
+            } => {
+                *private_key = map(private_key);
+                if let Some(mnemonic) = mnemonic {
+                    *mnemonic = map(mnemonic);
+                }
+            }
+            OutputMessage::Rare { private_key, .. } => *private_key = map(private_key),
+            _ => {}
         }
+        self
     }
 }
 
-fn preprocess_patterns(config: Option<&Vec<PatternConfig>>) -> Option<Vec<PatternRule>> {
-    let patterns = match config {
-        Some(p) => p,
-        None => return None,
-    };
+impl From<VanityError> for OutputMessage {
+    fn from(err: VanityError) -> Self {
+        OutputMessage::error(err.code, err.message)
+    }
+}
 
-    let mut rules = Vec::with_capacity(patterns.len());
-    for pattern in patterns {
-        if pattern.pattern.is_empty() {
-            continue;
+#[derive(Deserialize, Default, Clone)]
+struct InputMessage {
+    prefix: Option<String>,
+    prefixes: Option<Vec<String>>,
+    suffix: Option<String>,
+    contains: Option<String>,
+    #[serde(rename = "maxAttempts")]
+    max_attempts: Option<u64>,
+    #[serde(rename = "timeoutMs")]
+    timeout_ms: Option<u64>,
+    /// How often to emit `Progress`, clamped to 50..=10000 ms.
+    #[serde(rename = "reportIntervalMs")]
+    report_interval_ms: Option<u64>,
+    /// Emits only `Found`, `Rare`, `Error` and `Summary`, for consumers that
+    /// only want results.
+    quiet: Option<bool>,
+    threads: Option<usize>,
+    /// Parks workers while the machine is busy with other work.
+    adaptive: Option<bool>,
+    count: Option<usize>,
+    #[serde(rename = "keyFormat")]
+    key_format: Option<KeyFormat>,
+    mnemonic: Option<bool>,
+    #[serde(rename = "derivationPath")]
+    derivation_path: Option<String>,
+    /// 32-byte hex seed for reproducible runs; never for real wallets, see
+    /// the warning on `GenOptions::seed`.
+    seed: Option<String>,
+    /// `chacha` (the default), `os`, or `fast`, which is NOT cryptographically
+    /// secure and only meant for benchmarks.
+    rng: Option<RngKind>,
+    /// Runs until stopped, reporting only finds that outscore the previous
+    /// best instead of stopping after `count` matches.
+    #[serde(rename = "keepGoing")]
+    keep_going: Option<bool>,
+    /// Reseeds each worker's RNG from the OS after this many of its candidates.
+    #[serde(rename = "reseedInterval")]
+    reseed_interval: Option<u64>,
+    /// Pins each worker to its own CPU, physical cores first. Linux only.
+    #[serde(rename = "pinThreads")]
+    pin_threads: Option<bool>,
+    #[serde(rename = "jobId")]
+    job_id: Option<String>,
+    /// Cancels the running job with this id instead of starting a new one.
+    cancel: Option<String>,
+    /// Reports every running job instead of starting a new one.
+    status: Option<bool>,
+    /// Lists the ids of running jobs instead of starting a new one.
+    list: Option<bool>,
+    /// Reports the version and capabilities instead of starting a job.
+    version: Option<bool>,
+    /// Lists the active rare rules instead of starting a job.
+    #[serde(rename = "configInfo")]
+    config_info: Option<bool>,
+    /// Reloads the config for jobs started after this; on failure the
+    /// previous rules stay in effect.
+    #[serde(rename = "reloadConfig")]
+    reload_config: Option<bool>,
+    /// Measures raw keygen + base58 throughput instead of searching.
+    benchmark: Option<bool>,
+    seconds: Option<u64>,
+    /// Where rare hits are appended; defaults to `rare_wallets.txt`.
+    #[serde(rename = "rareOutputPath")]
+    rare_output_path: Option<String>,
+    /// Set to false to skip the rare-wallet file entirely. Rare hits then
+    /// exist only as `Rare` messages on stdout, so capture them there.
+    #[serde(rename = "writeRareFile")]
+    write_rare_file: Option<bool>,
+    /// Directory to save each found keypair to as `<address>.json`, always
+    /// the JSON byte array `solana-keygen` reads, whatever `keyFormat` is.
+    #[serde(rename = "outDir")]
+    out_dir: Option<String>,
+    /// File to append every `Found` and `Rare` message to, one JSON object
+    /// per line.
+    #[serde(rename = "jsonlOutputPath")]
+    jsonl_output_path: Option<String>,
+    /// CSV file to append every `Found` and `Rare` result to as
+    /// `address,private_key,pattern,attempts,elapsed_ms`.
+    #[serde(rename = "csvOutputPath")]
+    csv_output_path: Option<String>,
+    /// Replaces private keys and mnemonics on stdout with `<redacted>`, for
+    /// shared screens and logged pipelines. Files still get the full key, so
+    /// found keys need `outDir`, `jsonlOutputPath` or `csvOutputPath`.
+    #[serde(rename = "redactPrivateKey")]
+    redact_private_key: Option<bool>,
+    /// Encrypts every private key and mnemonic written to a file; see the
+    /// `seal` module for the format. Falls back to `VANITY_GEN_PASSPHRASE`,
+    /// and is deliberately not a command-line flag.
+    passphrase: Option<String>,
+    /// Renders each found address as a QR code: to stderr as text, or as
+    /// `<address>.png` under `qrOutDir` if that is set. Setting `qrOutDir`
+    /// alone turns it on.
+    qr: Option<bool>,
+    #[serde(rename = "qrOutDir")]
+    qr_out_dir: Option<String>,
+    /// Also renders the private key, as `<address>-key.png` under `qrOutDir`.
+    #[serde(rename = "qrPrivateKey")]
+    qr_private_key: Option<bool>,
+}
+
+/// Command-line flags. A job flag (`--prefix`, `--prefix-file`, `--suffix`,
+/// `--contains` or `--benchmark`) selects one-shot mode: a single job described
+/// by flags instead of a stdin message, with the same output. Without one the
+/// binary speaks the stdin protocol.
+#[derive(Parser)]
+#[command(version, about = "Solana vanity address generator")]
+struct Cli {
+    /// Wire format for stdin and stdout.
+    #[arg(long, value_enum, default_value_t)]
+    format: WireFormat,
+    /// Address prefix to search for; repeat it to accept any of several.
+    #[arg(long)]
+    prefix: Vec<String>,
+    /// File of prefixes, one per line, each ground as its own job tagged
+    /// `line-<n>`. Blank lines and `#` comments are skipped.
+    #[arg(long, conflicts_with = "prefix")]
+    prefix_file: Option<PathBuf>,
+    #[arg(long)]
+    suffix: Option<String>,
+    #[arg(long)]
+    contains: Option<String>,
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Park workers while other processes keep the CPUs busy.
+    #[arg(long)]
+    adaptive: bool,
+    /// Matches to find before exiting.
+    #[arg(long)]
+    count: Option<usize>,
+    #[arg(long)]
+    max_attempts: Option<u64>,
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+    #[arg(long)]
+    report_interval_ms: Option<u64>,
+    /// Emit only results, errors and the final summary.
+    #[arg(long)]
+    quiet: bool,
+    /// `base58` (the default) or `json`.
+    #[arg(long, value_parser = parse_key_format)]
+    key_format: Option<KeyFormat>,
+    #[arg(long)]
+    mnemonic: bool,
+    #[arg(long)]
+    derivation_path: Option<String>,
+    /// 64 hex characters. For tests and audits only: anyone with the seed can
+    /// regenerate every key.
+    #[arg(long)]
+    seed: Option<String>,
+    /// `chacha` (the default), `os`, or `fast`. `fast` is predictable: for
+    /// benchmarks only, never for real keys.
+    #[arg(long, value_parser = parse_rng)]
+    rng: Option<RngKind>,
+    /// Run until stopped, printing only finds rarer than the best so far.
+    #[arg(long)]
+    keep_going: bool,
+    /// Reseed each worker's RNG from the OS after this many of its keys.
+    #[arg(long)]
+    reseed_interval: Option<u64>,
+    /// Pin each worker to its own CPU, physical cores first (Linux only).
+    #[arg(long)]
+    pin_threads: bool,
+    /// Directory to save each found keypair to.
+    #[arg(long)]
+    out_dir: Option<String>,
+    /// File to append found and rare results to as JSON lines.
+    #[arg(long)]
+    jsonl_output_path: Option<String>,
+    /// CSV file to append found and rare results to.
+    #[arg(long)]
+    csv_output_path: Option<String>,
+    /// Keep private keys off stdout; they are only written to files.
+    #[arg(long)]
+    redact_private_key: bool,
+    /// Print a QR code of each found address to stderr.
+    #[arg(long)]
+    qr: bool,
+    /// Save the QR codes as PNGs here instead of printing them.
+    #[arg(long)]
+    qr_out_dir: Option<String>,
+    /// Also render the private key as a QR code.
+    #[arg(long)]
+    qr_private_key: bool,
+    #[arg(long)]
+    rare_output_path: Option<String>,
+    /// Only stream rare hits, without writing the rare-wallet file.
+    #[arg(long)]
+    no_rare_file: bool,
+    /// Rotate a rare-wallet file once it would grow past this many bytes,
+    /// renaming `rare_wallets.txt` to `rare_wallets.1.txt` and so on.
+    #[arg(long)]
+    rare_file_max_bytes: Option<u64>,
+    /// Rotated rare-wallet files to keep; older ones are deleted.
+    #[arg(long, default_value_t = 5)]
+    rare_file_rotations: usize,
+    /// Rare-pattern config to load instead of `config.json`; overrides
+    /// `SOLANA_VANITY_CONFIG`.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// In stdin mode, emit an `idle` message this often while no job runs,
+    /// so a supervisor can tell a quiet process from a hung one.
+    #[arg(long)]
+    heartbeat_ms: Option<u64>,
+    /// Measure raw keygen throughput instead of searching.
+    #[arg(long)]
+    benchmark: bool,
+    #[arg(long)]
+    seconds: Option<u64>,
+}
+
+impl Cli {
+    fn is_one_shot(&self) -> bool {
+        !self.prefix.is_empty()
+            || self.prefix_file.is_some()
+            || self.suffix.is_some()
+            || self.contains.is_some()
+            || self.benchmark
+    }
+}
+
+/// How protocol messages are framed on stdin and stdout.
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum WireFormat {
+    /// One JSON object per line.
+    #[default]
+    Json,
+    /// MessagePack, each frame prefixed with its length as a big-endian `u32`.
+    /// Text commands like `stop` are sent as MessagePack strings.
+    Msgpack,
+}
+
+impl From<Cli> for InputMessage {
+    fn from(cli: Cli) -> Self {
+        InputMessage {
+            prefixes: Some(cli.prefix),
+            suffix: cli.suffix,
+            contains: cli.contains,
+            max_attempts: cli.max_attempts,
+            timeout_ms: cli.timeout_ms,
+            report_interval_ms: cli.report_interval_ms,
+            quiet: Some(cli.quiet),
+            threads: cli.threads,
+            adaptive: Some(cli.adaptive),
+            count: cli.count,
+            key_format: cli.key_format,
+            mnemonic: Some(cli.mnemonic),
+            derivation_path: cli.derivation_path,
+            seed: cli.seed,
+            rng: cli.rng,
+            keep_going: Some(cli.keep_going),
+            reseed_interval: cli.reseed_interval,
+            pin_threads: Some(cli.pin_threads),
+            benchmark: Some(cli.benchmark),
+            seconds: cli.seconds,
+            rare_output_path: cli.rare_output_path,
+            write_rare_file: Some(!cli.no_rare_file),
+            out_dir: cli.out_dir,
+            jsonl_output_path: cli.jsonl_output_path,
+            csv_output_path: cli.csv_output_path,
+            redact_private_key: Some(cli.redact_private_key),
+            qr: Some(cli.qr),
+            qr_out_dir: cli.qr_out_dir,
+            qr_private_key: Some(cli.qr_private_key),
+            ..InputMessage::default()
         }
+    }
+}
 
-        let bytes = pattern.pattern.as_bytes();
-        let kind = if bytes.len() == 1 {
-            PatternKind::Single(bytes[0])
-        } else {
-            PatternKind::Sequence(bytes.to_vec())
-        };
+fn parse_key_format(value: &str) -> Result<KeyFormat, String> {
+    match value {
+        "base58" => Ok(KeyFormat::Base58),
+        "json" => Ok(KeyFormat::Json),
+        _ => Err(format!("unknown key format {:?}; expected base58 or json", value)),
+    }
+}
 
-        rules.push(PatternRule {
-            kind,
-            min_length: pattern.min_length,
-        });
+fn parse_rng(value: &str) -> Result<RngKind, String> {
+    match value {
+        "chacha" => Ok(RngKind::Chacha),
+        "os" => Ok(RngKind::Os),
+        "fast" => Ok(RngKind::Fast),
+        _ => Err(format!("unknown rng {:?}; expected chacha, os or fast", value)),
     }
+}
 
-    if rules.is_empty() {
-        None
-    } else {
-        Some(rules)
+const REPORT_INTERVAL_MS: u64 = 250;
+/// Bounds for `reportIntervalMs`.
+const MIN_REPORT_INTERVAL_MS: u64 = 50;
+const MAX_REPORT_INTERVAL_MS: u64 = 10_000;
+/// Longest the stdout writer holds back buffered messages.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(REPORT_INTERVAL_MS);
+const DEFAULT_BENCHMARK_SECONDS: u64 = 5;
+/// Keys a benchmark thread grinds between updates of the shared counter.
+const BENCHMARK_BATCH: u64 = 1024;
+/// How long shutdown waits for idle worker threads to exit.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Pools of finished jobs kept for later ones; any more are shut down.
+const MAX_IDLE_POOLS: usize = 4;
+const DEFAULT_RARE_OUTPUT_PATH: &str = "rare_wallets.txt";
+
+/// Where `Progress.percent` stops climbing, for display.
+const MAX_PROGRESS_PERCENT: f64 = 999.0;
+
+/// Expected attempts beyond which a search is reported as impractical.
+const IMPRACTICAL_ATTEMPTS: f64 = 1e15;
+
+/// Protocol features in every build, reported in `Version` so frontends can
+/// check for one instead of comparing version numbers. Named after the
+/// input fields they enable where there is one.
+const CAPABILITIES: &[&str] = &[
+    "prefix",
+    "prefixes",
+    "suffix",
+    "contains",
+    "wildcards",
+    "charClasses",
+    "patterns",
+    "mnemonic",
+    "seed",
+    "count",
+    "keepGoing",
+    "benchmark",
+    "cancel",
+    "status",
+    "list",
+    "configInfo",
+    "reloadConfig",
+    "msgpack",
+    "outDir",
+    "jsonlOutputPath",
+    "csvOutputPath",
+    "redactPrivateKey",
+    "passphrase",
+    "qr",
+    "adaptive",
+    "rng",
+    "reseedInterval",
+    "pinThreads",
+    "heartbeat",
+];
+
+/// A `Warning` for a search expected to take more than `IMPRACTICAL_ATTEMPTS`.
+fn impractical_search_warning(expected_attempts: Option<f64>) -> Option<OutputMessage> {
+    let expected = expected_attempts.filter(|&e| e > IMPRACTICAL_ATTEMPTS)?;
+    Some(OutputMessage::Warning {
+        message: format!(
+            "expected ~{:.2e} attempts; this search is unlikely to finish in practice",
+            expected
+        ),
+    })
+}
+
+fn version_message() -> OutputMessage {
+    let mut features: Vec<String> = CAPABILITIES.iter().map(|f| f.to_string()).collect();
+    if cfg!(feature = "metrics") {
+        features.push("metrics".to_string());
+    }
+    OutputMessage::Version {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: option_env!("VANITY_GEN_GIT_SHA").map(str::to_string),
+        threads: resolve_thread_count(None),
+        features,
     }
 }
 
-fn load_config() -> Option<Vec<PatternConfig>> {
-    let config_paths = vec![
+/// Names a config file to use instead of the default locations.
+const CONFIG_ENV: &str = "SOLANA_VANITY_CONFIG";
+
+/// Loads the rare-pattern rules from `explicit` (from `--config` or
+/// `SOLANA_VANITY_CONFIG`), or else from the first default location that
+/// holds a valid config. A named file that is missing or invalid is an
+/// error, with no fallback to the defaults.
+///
+/// Errors are emitted as they happen. `Ok(None)` means there is no config
+/// file; `Err` means there was one but none could be loaded.
+fn load_config(explicit: Option<&Path>) -> Result<Option<Vec<PatternConfig>>, ()> {
+    if let Some(path) = explicit {
+        if !path.exists() {
+            emit(&OutputMessage::error(
+                "config_not_found",
+                format!("config file {} does not exist", path.display()),
+            ));
+            return Err(());
+        }
+        return read_config(path).map(Some).ok_or(());
+    }
+
+    let config_paths = [
         PathBuf::from("config.json"),
         PathBuf::from("vanity_gen").join("config.json"),
     ];
+    let mut existing = config_paths.iter().filter(|path| path.exists()).peekable();
+    if existing.peek().is_none() {
+        return Ok(None);
+    }
+    existing.find_map(|path| read_config(path)).map(Some).ok_or(())
+}
 
-    for path in config_paths {
-        if path.exists() {
-            if let Ok(content) = fs::read_to_string(&path) {
-                if let Ok(config) = serde_json::from_str::<Config>(&content) {
-                    return Some(config.patterns);
-                }
-            }
+fn read_config(path: &Path) -> Option<Vec<PatternConfig>> {
+    let parsed = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|content| {
+            serde_json::from_str::<Config>(&content).map_err(|err| err.to_string())
+        });
+    match parsed {
+        Ok(config) => Some(config.patterns),
+        Err(err) => {
+            emit(&OutputMessage::error(
+                "config_parse_error",
+                format!("failed to load {}: {}", path.display(), err),
+            ));
+            None
         }
     }
+}
+
+fn parse_seed(seed: &str) -> Result<[u8; 32], String> {
+    let seed = seed.trim();
+    if seed.len() != 64 || !seed.is_ascii() {
+        return Err("seed must be exactly 64 hex characters".to_string());
+    }
+    // `from_str_radix` alone would also take a sign, as in `+f`.
+    if let Some(offset) = seed.bytes().position(|b| !b.is_ascii_hexdigit()) {
+        return Err(format!("seed contains invalid hex at offset {}", offset));
+    }
+
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&seed[i * 2..i * 2 + 2], 16).expect("checked hex digits");
+    }
+    Ok(bytes)
+}
 
-    None
+/// Wire form of an `OutputMessage`, tagged with the job it belongs to.
+#[derive(Serialize)]
+struct Envelope<'a> {
+    #[serde(rename = "jobId", skip_serializing_if = "Option::is_none")]
+    job_id: Option<&'a str>,
+    #[serde(flatten)]
+    message: &'a OutputMessage,
 }
 
-fn find_rare_pattern(address_bytes: &[u8], job_context: &JobContext) -> Option<String> {
-    let rules = match job_context.pattern_rules.as_ref() {
-        Some(r) => r,
-        None => return None,
+fn emit(msg: &OutputMessage) {
+    emit_for(None, msg);
+}
+
+/// Messages for the stdout writer thread.
+enum WriterCommand {
+    Emit {
+        job_id: Option<String>,
+        message: OutputMessage,
+    },
+    /// A rare hit to append to `path` as `entry` before emitting `message`.
+    /// The two differ only in how the secrets are shown.
+    Rare {
+        job_id: String,
+        path: PathBuf,
+        entry: OutputMessage,
+        message: OutputMessage,
+    },
+    /// A result to append to the results file at `path`, without emitting.
+    Record {
+        job_id: String,
+        path: PathBuf,
+        format: RecordFormat,
+        message: OutputMessage,
+    },
+    Shutdown,
+}
+
+/// How a results file lays out each `Found` or `Rare` message.
+#[derive(Clone, Copy)]
+enum RecordFormat {
+    /// The stdout JSON envelope, one per line.
+    Jsonl,
+    /// `address,private_key,pattern,attempts,elapsed_ms` rows under a header.
+    Csv,
+}
+
+/// Header row of CSV results files.
+const CSV_HEADER: &str = "address,private_key,pattern,attempts,elapsed_ms\n";
+
+/// When the writer rotates rare-wallet files. Applies to every file, across
+/// all jobs.
+#[derive(Clone, Copy)]
+struct RareRotation {
+    /// Size a file may reach; `None` lets it grow forever.
+    max_bytes: Option<u64>,
+    /// Rotated files kept next to the live one.
+    keep: usize,
+}
+
+/// Sender half of the writer channel, set once in `main`.
+static OUTPUT: OnceLock<Sender<WriterCommand>> = OnceLock::new();
+
+/// Set once stdout is a broken pipe: nobody is reading, so every job stops.
+/// Rare hits still reach their files.
+static STDOUT_CLOSED: AtomicBool = AtomicBool::new(false);
+
+fn emit_for(job_id: Option<&str>, msg: &OutputMessage) {
+    if let Some(output) = OUTPUT.get() {
+        let _ = output.send(WriterCommand::Emit {
+            job_id: job_id.map(str::to_string),
+            message: msg.clone(),
+        });
+    } else {
+        let mut stdout = io::stdout().lock();
+        let _ = write_message(&mut stdout, WireFormat::Json, job_id, msg)
+            .and_then(|()| stdout.flush());
+    }
+}
+
+fn write_message(
+    out: &mut impl Write,
+    format: WireFormat,
+    job_id: Option<&str>,
+    msg: &OutputMessage,
+) -> io::Result<()> {
+    let envelope = Envelope {
+        job_id,
+        message: msg,
     };
+    match format {
+        WireFormat::Json => match serde_json::to_string(&envelope) {
+            Ok(json) => writeln!(out, "{}", json),
+            Err(_) => Ok(()),
+        },
+        WireFormat::Msgpack => match rmp_serde::to_vec_named(&envelope) {
+            Ok(frame) => {
+                out.write_all(&(frame.len() as u32).to_be_bytes())?;
+                out.write_all(&frame)
+            }
+            Err(_) => Ok(()),
+        },
+    }
+}
+
+/// Buffered stdout for the writer thread, flushed on a timer rather than
+/// once per message.
+struct StdoutSink<W: Write> {
+    out: BufWriter<W>,
+    format: WireFormat,
+    /// Set once `out` turns out to be a broken pipe; `STDOUT_CLOSED` for stdout.
+    closed: &'static AtomicBool,
+    /// Whether anything was written since the last flush.
+    pending: bool,
+    last_flush: Instant,
+}
+
+impl<W: Write> StdoutSink<W> {
+    fn new(out: W, format: WireFormat, closed: &'static AtomicBool) -> Self {
+        Self {
+            out: BufWriter::new(out),
+            format,
+            closed,
+            pending: false,
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn write(&mut self, job_id: Option<&str>, message: &OutputMessage) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        self.pending = true;
+        let result = write_message(&mut self.out, self.format, job_id, message);
+        self.check(result);
+    }
+
+    fn flush(&mut self) {
+        if !self.pending || self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        self.pending = false;
+        self.last_flush = Instant::now();
+        let result = self.out.flush();
+        self.check(result);
+    }
 
-    for rule in rules {
-        match &rule.kind {
-            PatternKind::Single(target) => {
-                let mut repeat_count = 0;
-                for &byte in address_bytes {
-                    if byte == *target {
-                        repeat_count += 1;
+    /// How long buffered output may still wait, or `None` if nothing is buffered.
+    fn flush_due_in(&self) -> Option<Duration> {
+        self.pending
+            .then(|| FLUSH_INTERVAL.saturating_sub(self.last_flush.elapsed()))
+    }
+
+    fn check(&self, result: io::Result<()>) {
+        if result.is_err_and(|err| err.kind() == io::ErrorKind::BrokenPipe) {
+            tracing::warn!("stdout closed; stopping all jobs");
+            self.closed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Owns stdout so workers never contend on its lock and every message goes
+/// out as one whole line. Output is batched, but never held back longer
+/// than `FLUSH_INTERVAL`.
+fn spawn_stdout_writer(format: WireFormat, rotation: RareRotation) -> JoinHandle<()> {
+    spawn_writer(|| io::stdout().lock(), format, rotation)
+}
+
+/// `spawn_stdout_writer`, writing to what `open` returns on the writer thread.
+fn spawn_writer<W: Write>(
+    open: impl FnOnce() -> W + Send + 'static,
+    format: WireFormat,
+    rotation: RareRotation,
+) -> JoinHandle<()> {
+    let (tx, rx) = mpsc::channel();
+    OUTPUT.set(tx).expect("stdout writer started twice");
+    thread::spawn(move || {
+        let mut stdout = StdoutSink::new(open(), format, &STDOUT_CLOSED);
+        let mut rare_files: HashMap<PathBuf, fs::File> = HashMap::new();
+        let mut record_files: HashMap<PathBuf, fs::File> = HashMap::new();
+        loop {
+            let command = match stdout.flush_due_in() {
+                Some(wait) => match rx.recv_timeout(wait) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => {
+                        stdout.flush();
                         continue;
                     }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match rx.recv() {
+                    Ok(command) => command,
+                    Err(_) => break,
+                },
+            };
 
-                    if repeat_count >= rule.min_length {
-                        let pattern = vec![*target; repeat_count];
-                        if let Ok(found_pattern) = String::from_utf8(pattern) {
-                            return Some(found_pattern);
-                        }
+            match command {
+                WriterCommand::Emit { job_id, message } => {
+                    stdout.write(job_id.as_deref(), &message);
+                    // A match is what the consumer is waiting for; never sit on it.
+                    if matches!(message, OutputMessage::Found { .. }) {
+                        stdout.flush();
                     }
-                    repeat_count = 0;
                 }
-
-                if repeat_count >= rule.min_length {
-                    let pattern = vec![*target; repeat_count];
-                    if let Ok(found_pattern) = String::from_utf8(pattern) {
-                        return Some(found_pattern);
+                WriterCommand::Rare {
+                    job_id,
+                    path,
+                    entry,
+                    message,
+                } => {
+                    let result = append_rare_wallet(&mut rare_files, &path, &entry, rotation);
+                    if let Err(err) = result {
+                        let error = OutputMessage::error(
+                            "rare_write_failed",
+                            format!("could not save rare wallet to {}: {}", path.display(), err),
+                        );
+                        stdout.write(Some(&job_id), &error);
                     }
+                    stdout.write(Some(&job_id), &message);
                 }
-            }
-            PatternKind::Sequence(pattern_bytes) => {
-                let pattern_len = pattern_bytes.len();
-                if pattern_len == 0 || address_bytes.len() < pattern_len * rule.min_length {
-                    continue;
+                WriterCommand::Record {
+                    job_id,
+                    path,
+                    format,
+                    message,
+                } => {
+                    let result = append_record(&mut record_files, &path, format, &job_id, &message);
+                    if let Err(err) = result {
+                        let code = match format {
+                            RecordFormat::Jsonl => "jsonl_write_failed",
+                            RecordFormat::Csv => "csv_write_failed",
+                        };
+                        let error = OutputMessage::error(
+                            code,
+                            format!("could not append result to {}: {}", path.display(), err),
+                        );
+                        stdout.write(Some(&job_id), &error);
+                    }
                 }
+                WriterCommand::Shutdown => break,
+            }
+            if stdout.flush_due_in() == Some(Duration::ZERO) {
+                stdout.flush();
+            }
+        }
+        stdout.flush();
+    })
+}
 
-                let mut index = 0;
-                while index + pattern_len <= address_bytes.len() {
-                    if &address_bytes[index..index + pattern_len] == pattern_bytes {
-                        let mut match_count = 1;
-                        let mut cursor = index + pattern_len;
-
-                        while cursor + pattern_len <= address_bytes.len()
-                            && &address_bytes[cursor..cursor + pattern_len] == pattern_bytes
-                        {
-                            match_count += 1;
-                            cursor += pattern_len;
-                        }
+/// Appends a rare hit to `path`, opening each file on first use and keeping
+/// it open for the rest of the run. Rotates the file first if the entry would
+/// take it past `rotation.max_bytes`, so entries are never split.
+fn append_rare_wallet(
+    rare_files: &mut HashMap<PathBuf, fs::File>,
+    path: &Path,
+    message: &OutputMessage,
+    rotation: RareRotation,
+) -> io::Result<()> {
+    let OutputMessage::Rare {
+        address,
+        private_key,
+        pattern,
+        ..
+    } = message
+    else {
+        return Ok(());
+    };
+    // Unix seconds: sortable and free of time zones.
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let entry = format!(
+        "Time: {}\nPattern: {}\nAddress: {}\nPrivate Key: {}\n\n",
+        time, pattern, address, private_key
+    );
+    if !rare_files.contains_key(path) {
+        rare_files.insert(path.to_path_buf(), open_for_append(path)?);
+    }
+    let file = rare_files.get_mut(path).expect("rare file was just opened");
+    if let Some(max_bytes) = rotation.max_bytes {
+        let len = file.metadata()?.len();
+        if len > 0 && len + entry.len() as u64 > max_bytes {
+            rotate_rare_output(path, rotation.keep)?;
+            *file = open_for_append(path)?;
+        }
+    }
+    file.write_all(entry.as_bytes())
+}
 
-                        if match_count >= rule.min_length {
-                            let mut repeated = Vec::with_capacity(pattern_len * match_count);
-                            for _ in 0..match_count {
-                                repeated.extend_from_slice(pattern_bytes);
-                            }
+/// Appends `message` to `path` as one line in `format`. A CSV file gets its
+/// header first if it is empty.
+fn append_record(
+    record_files: &mut HashMap<PathBuf, fs::File>,
+    path: &Path,
+    format: RecordFormat,
+    job_id: &str,
+    message: &OutputMessage,
+) -> io::Result<()> {
+    if !record_files.contains_key(path) {
+        let mut file = open_for_append(path)?;
+        if matches!(format, RecordFormat::Csv) && file.metadata()?.len() == 0 {
+            file.write_all(CSV_HEADER.as_bytes())?;
+        }
+        record_files.insert(path.to_path_buf(), file);
+    }
+    let file = record_files.get_mut(path).expect("record file was just opened");
+    // One write per line, so a failure can't leave half a record behind.
+    let mut line = Vec::new();
+    match format {
+        RecordFormat::Jsonl => write_message(&mut line, WireFormat::Json, Some(job_id), message)?,
+        RecordFormat::Csv => {
+            let (address, private_key, pattern, attempts, elapsed_ms) = match message {
+                OutputMessage::Found {
+                    address,
+                    private_key,
+                    matched_prefix,
+                    attempts,
+                    elapsed_ms,
+                    ..
+                } => (address, private_key, matched_prefix.as_deref(), attempts, elapsed_ms),
+                OutputMessage::Rare {
+                    address,
+                    private_key,
+                    pattern,
+                    attempts,
+                    elapsed_ms,
+                    ..
+                } => (address, private_key, Some(pattern.as_str()), attempts, elapsed_ms),
+                _ => return Ok(()),
+            };
+            writeln!(
+                line,
+                "{},{},{},{},{}",
+                csv_field(address),
+                csv_field(private_key),
+                csv_field(pattern.unwrap_or("")),
+                attempts,
+                elapsed_ms
+            )?;
+        }
+    }
+    file.write_all(&line)
+}
 
-                            if let Ok(found_pattern) = String::from_utf8(repeated) {
-                                return Some(found_pattern);
-                            }
-                        }
+/// Quotes a CSV text field. Base58 never needs it, but patterns and future
+/// key formats might.
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
 
-                        index = cursor;
-                        continue;
-                    }
+/// Shifts `path`'s rotations up by one, dropping the oldest beyond `keep`,
+/// and moves `path` itself to rotation 1.
+fn rotate_rare_output(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let oldest = rotated_path(path, keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..keep).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
 
-                    index += 1;
+/// `rare_wallets.txt` with rotation `index` is `rare_wallets.<index>.txt`.
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Opens `path` for appending, creating it and any missing parent directories.
+fn open_for_append(path: &Path) -> io::Result<fs::File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Writes `contents` to `<dir>/<address>.json`; see `write_private_file`.
+fn write_keypair_file(dir: &Path, address: &str, contents: &str) -> io::Result<PathBuf> {
+    write_private_file(dir, &format!("{}.json", address), contents.as_bytes())
+}
+
+/// Writes `contents` to `<dir>/<file_name>`, readable only by the owner, via
+/// a temp file and rename, so a crash never leaves a half-written secret
+/// behind.
+fn write_private_file(dir: &Path, file_name: &str, contents: &[u8]) -> io::Result<PathBuf> {
+    let path = dir.join(file_name);
+    let temp_path = dir.join(format!(".{}.tmp", file_name));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let result = options.open(&temp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = result.and_then(|()| fs::rename(&temp_path, &path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    Ok(path)
+}
+
+/// Flushes everything queued so far and stops the writer thread.
+fn shutdown_writer(writer: JoinHandle<()>) {
+    if let Some(output) = OUTPUT.get() {
+        let _ = output.send(WriterCommand::Shutdown);
+    }
+    let _ = writer.join();
+}
+
+/// What the stdin reader hands the main loop.
+enum InputCommand {
+    /// `stop`: cancel every job and exit.
+    Stop,
+    /// `cancel`: cancel every job but keep reading.
+    CancelAll,
+    Message(Box<InputMessage>),
+    /// Input that didn't parse, with the reason.
+    Invalid(String),
+}
+
+/// Largest msgpack frame accepted on stdin.
+const MAX_INPUT_FRAME_LEN: usize = 1 << 20;
+
+/// Reads and parses stdin on its own thread so commands like `cancel` can
+/// arrive while a job is grinding, and so the control loop in `main` never
+/// blocks on input and can reap finished jobs and send heartbeats meanwhile.
+fn spawn_stdin_reader(format: WireFormat) -> Receiver<InputCommand> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        match format {
+            WireFormat::Json => {
+                for line in stdin.lines() {
+                    let line = match line {
+                        Ok(l) => l,
+                        Err(_) => break,
+                    };
+                    let commands = match line.trim() {
+                        "" => continue,
+                        "stop" => vec![InputCommand::Stop],
+                        "cancel" => vec![InputCommand::CancelAll],
+                        line => parse_line(line),
+                    };
+                    for command in commands {
+                        if sender.send(command).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            WireFormat::Msgpack => {
+                while let Ok(frame) = read_frame(&mut stdin) {
+                    let command = match frame {
+                        Some(frame) => parse_frame(&frame),
+                        None => InputCommand::Invalid(format!(
+                            "input frame is larger than {} bytes",
+                            MAX_INPUT_FRAME_LEN
+                        )),
+                    };
+                    if sender.send(command).is_err() {
+                        break;
+                    }
                 }
             }
         }
-    }
+    });
+    receiver
+}
 
-    None
+/// Parses a JSON input line. Clients that batch their writes may put several
+/// objects on one line; each becomes its own command, in order, up to the
+/// first one that doesn't parse.
+fn parse_line(line: &str) -> Vec<InputCommand> {
+    let mut commands = Vec::new();
+    for message in serde_json::Deserializer::from_str(line).into_iter() {
+        match message {
+            Ok(msg) => commands.push(InputCommand::Message(Box::new(msg))),
+            Err(err) => {
+                commands.push(InputCommand::Invalid(format!(
+                    "could not parse input line: {}",
+                    err
+                )));
+                break;
+            }
+        }
+    }
+    commands
 }
 
-fn encode_private_key(secret: &[u8; 32], public: &[u8; 32]) -> String {
-    let mut keypair_bytes = [0u8; 64];
-    keypair_bytes[..32].copy_from_slice(secret);
-    keypair_bytes[32..].copy_from_slice(public);
-    fd_bs58::encode_64(keypair_bytes)
+/// Reads one length-prefixed frame, or skips it and returns `None` if it is
+/// too large to accept.
+fn read_frame(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    input.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_INPUT_FRAME_LEN {
+        io::copy(&mut Read::take(input, len as u64), &mut io::sink())?;
+        return Ok(None);
+    }
+    let mut frame = vec![0u8; len];
+    input.read_exact(&mut frame)?;
+    Ok(Some(frame))
 }
 
-fn main() {
-    let config = load_config();
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    for line in stdin.lock().lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => break,
+fn parse_frame(frame: &[u8]) -> InputCommand {
+    if let Ok(text) = rmp_serde::from_slice::<String>(frame) {
+        return match text.trim() {
+            "stop" => InputCommand::Stop,
+            "cancel" => InputCommand::CancelAll,
+            other => InputCommand::Invalid(format!("unknown command {:?}", other)),
         };
+    }
+    match rmp_serde::from_slice(frame) {
+        Ok(msg) => InputCommand::Message(Box::new(msg)),
+        Err(err) => InputCommand::Invalid(format!("could not parse input frame: {}", err)),
+    }
+}
 
-        let line_trimmed = line.trim();
-        if line_trimmed == "stop" {
-            break;
+/// Worker threads kept between jobs, so a stream of short jobs doesn't start
+/// and stop threads each time. Every running job leases a pool of its own,
+/// so concurrent jobs never queue behind each other's workers.
+struct WorkerPool {
+    cache: Arc<PoolCache>,
+    /// Receives one message per worker thread as it exits.
+    exited: Receiver<()>,
+}
+
+struct PoolCache {
+    /// Pools handed back by finished jobs, at most `MAX_IDLE_POOLS`.
+    idle: Mutex<Vec<rayon::ThreadPool>>,
+    /// Worker threads started so far, across every pool.
+    started: AtomicUsize,
+    exit_sender: Sender<()>,
+}
+
+/// A job's pool, returned to the `WorkerPool` when the lease is dropped.
+struct PoolLease {
+    pool: Option<rayon::ThreadPool>,
+    cache: Arc<PoolCache>,
+}
+
+impl WorkerPool {
+    fn new() -> Self {
+        let (exit_sender, exited) = mpsc::channel();
+        Self {
+            cache: Arc::new(PoolCache {
+                idle: Mutex::new(Vec::new()),
+                started: AtomicUsize::new(0),
+                exit_sender,
+            }),
+            exited,
         }
+    }
 
-        let input: InputMessage = match serde_json::from_str(&line_trimmed) {
-            Ok(msg) => msg,
-            Err(_) => continue,
+    /// A pool of exactly `threads` threads: an idle one if one fits, or else
+    /// a new one.
+    fn lease(&self, threads: usize) -> Result<PoolLease, rayon::ThreadPoolBuildError> {
+        let reused = {
+            let mut idle = self.cache.idle.lock().unwrap_or_else(PoisonError::into_inner);
+            idle.iter()
+                .position(|pool| pool.current_num_threads() == threads)
+                .map(|index| idle.swap_remove(index))
         };
-
-        let prefix = match input.prefix {
-            Some(p) => p,
-            None => continue,
+        let pool = match reused {
+            Some(pool) => pool,
+            None => {
+                let exit_sender = self.cache.exit_sender.clone();
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|index| format!("worker-{}", index))
+                    .exit_handler(move |_| {
+                        let _ = exit_sender.send(());
+                    })
+                    .build()?;
+                self.cache.started.fetch_add(threads, Ordering::Relaxed);
+                pool
+            }
         };
+        Ok(PoolLease {
+            pool: Some(pool),
+            cache: Arc::clone(&self.cache),
+        })
+    }
 
-        let job_context = Arc::new(JobContext::new(&prefix, config.as_ref()));
-        let num_threads = num_cpus::get();
-        let stop_flag = Arc::new(AtomicBool::new(false));
-        let file_lock = Arc::new(Mutex::new(()));
-        let shared_attempts_counter = Arc::new(AtomicU64::new(0));
-        let mut handles = vec![];
-
-        for tid in 0..num_threads {
-            let stop_flag_clone = Arc::clone(&stop_flag);
-            let file_lock_clone = Arc::clone(&file_lock);
-            let job_context_clone = Arc::clone(&job_context);
-            let attempts_counter_clone = Arc::clone(&shared_attempts_counter);
-
-            let handle = thread::spawn(move || {
-                generate_vanity(
-                    tid,
-                    job_context_clone,
-                    stop_flag_clone,
-                    attempts_counter_clone,
-                    file_lock_clone,
-                )
+    /// Releases the idle threads and waits for every worker thread to exit.
+    /// Call after joining every job.
+    fn drain(self) {
+        let threads = self.cache.started.load(Ordering::Relaxed);
+        self.cache
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        for _ in 0..threads {
+            if self.exited.recv_timeout(DRAIN_TIMEOUT).is_err() {
+                tracing::warn!("worker threads did not exit in time");
+                break;
+            }
+        }
+    }
+}
+
+impl std::ops::Deref for PoolLease {
+    type Target = rayon::ThreadPool;
+
+    fn deref(&self) -> &rayon::ThreadPool {
+        self.pool.as_ref().expect("pool is only taken on drop")
+    }
+}
+
+impl Drop for PoolLease {
+    fn drop(&mut self) {
+        let mut idle = self.cache.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE_POOLS {
+            idle.extend(self.pool.take());
+        }
+    }
+}
+
+/// A running job: its supervisor thread owns the workers and progress loop.
+struct JobHandle {
+    cancel_flag: Arc<AtomicBool>,
+    supervisor: JoinHandle<()>,
+    prefixes: Vec<String>,
+    started: Instant,
+    /// Live attempt count, for status reports.
+    attempts: Arc<AtomicU64>,
+}
+
+impl JobHandle {
+    fn status(&self, job_id: &str) -> JobStatus {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed();
+        JobStatus {
+            job_id: job_id.to_string(),
+            prefixes: self.prefixes.clone(),
+            attempts,
+            rate: attempts as f64 / elapsed.as_secs_f64(),
+            elapsed_ms: elapsed.as_millis() as u64,
+        }
+    }
+}
+
+/// A validated search plus the settings only the binary cares about: where
+/// its results are written and how often it reports.
+struct Job {
+    id: String,
+    context: JobContext,
+    /// Where rare hits are saved, or `None` to only stream them.
+    rare_output_path: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    expected_attempts: Option<f64>,
+    /// How often to emit `Progress`; `None` in quiet mode.
+    report_interval: Option<Duration>,
+    /// Leaves out `Stopped`, `Timeout` and `Exhausted`; `Summary` says as much.
+    quiet: bool,
+    /// Attempts so far, shared with the job's handle.
+    attempts: Arc<AtomicU64>,
+    /// Results files every result is also appended to.
+    record_paths: Vec<(PathBuf, RecordFormat)>,
+    /// Keeps secrets off stdout; see `InputMessage::redact_private_key`.
+    redact_private_key: bool,
+    /// Encrypts secrets on their way to files, if the job has a passphrase.
+    sealer: Option<KeySealer>,
+    qr: Option<QrOutput>,
+}
+
+/// How a job renders QR codes of its finds.
+struct QrOutput {
+    /// Directory for PNGs; `None` prints text codes to stderr instead.
+    dir: Option<PathBuf>,
+    private_key: bool,
+}
+
+impl Job {
+    fn emit(&self, msg: &OutputMessage) {
+        emit_for(Some(&self.id), msg);
+    }
+
+    /// `message` as the job's files store it.
+    fn for_files(&self, message: OutputMessage) -> OutputMessage {
+        match &self.sealer {
+            Some(sealer) => message.map_secrets(|secret| sealer.seal(secret)),
+            None => message,
+        }
+    }
+
+    /// `message` as the job shows it on stdout.
+    fn for_stdout(&self, message: OutputMessage) -> OutputMessage {
+        if self.redact_private_key {
+            message.redacted()
+        } else {
+            message
+        }
+    }
+
+    /// Appends a result, already passed through `for_files`, to each of the
+    /// job's results files.
+    fn record(&self, message: &OutputMessage) {
+        let Some(output) = OUTPUT.get() else {
+            return;
+        };
+        for (path, format) in &self.record_paths {
+            let _ = output.send(WriterCommand::Record {
+                job_id: self.id.clone(),
+                path: path.clone(),
+                format: *format,
+                message: message.clone(),
             });
+        }
+    }
 
-            handles.push(handle);
+    /// Writes `found`'s keypair file under `dir` in `solana-keygen` form,
+    /// sealed if the job encrypts.
+    fn write_key_file(&self, dir: &Path, found: &VanityMatch) -> io::Result<PathBuf> {
+        let secret = Zeroizing::new(found.signing_key.to_bytes());
+        let public = found.signing_key.verifying_key().to_bytes();
+        let keypair = Zeroizing::new(KeyFormat::Json.encode(&secret, &public));
+        match &self.sealer {
+            Some(sealer) => write_keypair_file(dir, &found.address, &sealer.seal(&keypair)),
+            None => write_keypair_file(dir, &found.address, &keypair),
         }
+    }
 
-        let mut last_report = Instant::now();
+    /// Renders `found`'s address, and its private key if asked, as QR codes.
+    /// A PNG of the key holds what the job's files would: sealed if the job
+    /// encrypts.
+    fn render_qr(&self, qr: &QrOutput, found: &VanityMatch) -> Result<(), String> {
+        // (caption on stderr, file name suffix, contents)
+        let mut codes = vec![(
+            found.address.as_str(),
+            "",
+            Zeroizing::new(found.address.clone()),
+        )];
+        if qr.private_key {
+            let private_key = match (&self.sealer, &qr.dir) {
+                (Some(sealer), Some(_)) => sealer.seal(&found.private_key),
+                _ => found.private_key.clone(),
+            };
+            codes.push(("Private key", "-key", Zeroizing::new(private_key)));
+        }
 
-        loop {
-            thread::sleep(Duration::from_millis(50));
-
-            let now = Instant::now();
-            if now.duration_since(last_report).as_millis() >= REPORT_INTERVAL_MS as u128 {
-                let total_attempts = shared_attempts_counter.load(Ordering::Relaxed);
-                let msg = OutputMessage::Progress { tid: 0, attempts: total_attempts };
-                if let Ok(json) = serde_json::to_string(&msg) {
-                    let _ = writeln!(stdout, "{}", json);
-                    let _ = stdout.flush();
+        for (caption, suffix, data) in codes {
+            let code = QrCode::new(data.as_bytes()).map_err(|err| err.to_string())?;
+            match &qr.dir {
+                Some(dir) => {
+                    let mut png = Vec::new();
+                    code.render::<image::Luma<u8>>()
+                        .build()
+                        .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+                        .map_err(|err| err.to_string())?;
+                    let file_name = format!("{}{}.png", found.address, suffix);
+                    write_private_file(dir, &file_name, &png).map_err(|err| err.to_string())?;
+                }
+                None => {
+                    // Inverted so the code reads dark-on-light on a dark terminal.
+                    let text = code
+                        .render::<Dense1x2>()
+                        .dark_color(Dense1x2::Light)
+                        .light_color(Dense1x2::Dark)
+                        .build();
+                    eprintln!("{}\n{}", caption, text);
                 }
-                last_report = now;
             }
+        }
+        Ok(())
+    }
 
-            let all_done = handles.iter().all(|h| h.is_finished());
-            if all_done {
-                break;
+    /// Saves `found` under `outDir` if one was set, then emits it.
+    fn emit_found(&self, found: VanityMatch) {
+        let key_file = match self.out_dir.as_deref() {
+            Some(dir) => match self.write_key_file(dir, &found) {
+                Ok(path) => Some(path.display().to_string()),
+                Err(err) => {
+                    self.emit(&OutputMessage::error(
+                        "keypair_write_failed",
+                        format!("could not save keypair for {}: {}", found.address, err),
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some(qr) = &self.qr {
+            if let Err(err) = self.render_qr(qr, &found) {
+                self.emit(&OutputMessage::error(
+                    "qr_failed",
+                    format!("could not render a QR code for {}: {}", found.address, err),
+                ));
             }
         }
 
-        stop_flag.store(true, Ordering::Relaxed);
+        let message = OutputMessage::Found {
+            address: found.address,
+            private_key: found.private_key,
+            attempts: found.attempts,
+            elapsed_ms: found.elapsed_ms,
+            position: found.position,
+            matched_prefix: found.matched_prefix,
+            mnemonic: found.mnemonic,
+            key_file,
+            score: found.score,
+        };
+        self.record(&self.for_files(message.clone()));
+        self.emit(&self.for_stdout(message));
+    }
 
-        for handle in handles {
-            let _ = handle.join();
+    /// Emits a `Rare` message, after the writer has saved it to disk unless
+    /// the job opted out of the file.
+    fn emit_rare(&self, hit: RareHit) {
+        let message = OutputMessage::Rare {
+            address: hit.address,
+            private_key: hit.private_key,
+            pattern: hit.pattern,
+            position: hit.position,
+            score: hit.score,
+            attempts: hit.attempts,
+            elapsed_ms: hit.elapsed_ms,
+            label: hit.label,
+            count: hit.count,
+        };
+        let entry = self.for_files(message.clone());
+        let message = self.for_stdout(message);
+        self.record(&entry);
+        let output_file = hit.output_file;
+        let Some(path) = self
+            .rare_output_path
+            .as_ref()
+            .map(|path| output_file.unwrap_or_else(|| path.clone()))
+        else {
+            self.emit(&message);
+            return;
+        };
+        if let Some(output) = OUTPUT.get() {
+            let _ = output.send(WriterCommand::Rare {
+                job_id: self.id.clone(),
+                path,
+                entry,
+                message,
+            });
         }
     }
 }
 
-fn generate_vanity(
-    _tid: usize,
-    job_context: Arc<JobContext>,
-    stop_flag: Arc<AtomicBool>,
-    attempts_counter: Arc<AtomicU64>,
-    file_lock: Arc<Mutex<()>>,
-) {
-    let mut rng = ChaCha20Rng::from_rng(OsRng).expect("Failed to seed RNG");
-    let mut secret_bytes = [0u8; 32];
-    let job_context_ref = job_context.as_ref();
+/// Validates `input` and starts it as job `job_id`, or emits an `Error` and
+/// returns `None` if the request can't run.
+fn start_job(
+    job_id: String,
+    input: InputMessage,
+    config: Option<&Vec<PatternConfig>>,
+    workers: &WorkerPool,
+) -> Option<JobHandle> {
+    let emit_job = |msg: &OutputMessage| emit_for(Some(&job_id), msg);
+    // Quiet jobs report only results, errors and their summary.
+    let quiet = input.quiet.unwrap_or(false);
+    let emit_notice = |msg: &OutputMessage| {
+        if !quiet {
+            emit_job(msg);
+        }
+    };
 
-    while !stop_flag.load(Ordering::Relaxed) {
-        rand::RngCore::fill_bytes(&mut rng, &mut secret_bytes);
-        let signing_key = SigningKey::from_bytes(&secret_bytes);
-        
-        let public_key = signing_key.verifying_key();
-        let public_key_bytes = public_key.as_bytes();
-        let address = fd_bs58::encode_32(public_key_bytes);
-        let attempts = attempts_counter.fetch_add(1, Ordering::Relaxed) + 1;
-        let address_bytes = address.as_bytes();
-
-        if let Some(pattern) = find_rare_pattern(address_bytes, job_context_ref) {
-            let secret_bytes_key = signing_key.to_bytes();
-            let private_key = encode_private_key(&secret_bytes_key, public_key_bytes);
-            
-            let _lock = file_lock.lock().unwrap();
-            if let Ok(mut file) = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("rare_wallets.txt")
-            {
-                let _ = writeln!(file, "Pattern: {}\nAddress: {}\nPrivate Key: {}\n", pattern, address, private_key);
-            }
-            drop(_lock);
-            
-            let msg = OutputMessage::Rare {
-                address: address.clone(),
-                private_key,
-                pattern,
-                attempts,
+    let seed = match input.seed.as_deref().map(parse_seed).transpose() {
+        Ok(seed) => seed,
+        Err(message) => {
+            emit_job(&OutputMessage::error("invalid_seed", message));
+            return None;
+        }
+    };
+    let criteria = VanityCriteria {
+        prefixes: input
+            .prefix
+            .iter()
+            .chain(input.prefixes.iter().flatten())
+            .cloned()
+            .collect(),
+        suffix: input.suffix.clone(),
+        contains: input.contains.clone(),
+        patterns: config.cloned().unwrap_or_default(),
+    };
+    let options = GenOptions {
+        threads: input.threads,
+        max_attempts: input.max_attempts,
+        timeout: input.timeout_ms.map(Duration::from_millis),
+        count: input.count.unwrap_or(1) as u64,
+        key_format: input.key_format.unwrap_or_default(),
+        mnemonic: input.mnemonic.unwrap_or(false),
+        derivation_path: input.derivation_path.clone(),
+        seed,
+        rng: input.rng.unwrap_or_default(),
+        reseed_interval: input.reseed_interval,
+        keep_going: input.keep_going.unwrap_or(false),
+        adaptive: input.adaptive.unwrap_or(false),
+        pin_threads: input.pin_threads.unwrap_or(false),
+        ..GenOptions::default()
+    };
+    let context = match JobContext::new(&criteria, &options) {
+        Ok(context) => context,
+        Err(err) => {
+            emit_job(&err.into());
+            return None;
+        }
+    };
+    for err in context.skipped_patterns() {
+        emit_job(&err.clone().into());
+    }
+    if options.rng == RngKind::Fast {
+        emit_notice(&OutputMessage::Warning {
+            message: "rng \"fast\" is NOT cryptographically secure: keys from this job can be \
+                      predicted and must never hold funds"
+                .to_string(),
+        });
+    }
+    for message in context.prefix_warnings() {
+        emit_notice(&OutputMessage::Warning {
+            message: message.clone(),
+        });
+    }
+
+    let rare_output_path = if !input.write_rare_file.unwrap_or(true) {
+        None
+    } else {
+        Some(PathBuf::from(
+            input
+                .rare_output_path
+                .as_deref()
+                .unwrap_or(DEFAULT_RARE_OUTPUT_PATH),
+        ))
+    };
+    let mut out_dir = None;
+    if let Some(dir) = input.out_dir.as_deref() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            emit_job(&OutputMessage::error(
+                "out_dir_unwritable",
+                format!("cannot create output directory {}: {}", dir, err),
+            ));
+            return None;
+        }
+        out_dir = Some(PathBuf::from(dir));
+    }
+    // Fail fast rather than losing every rare hit to a bad path.
+    let rare_paths: Vec<&Path> = match &rare_output_path {
+        Some(default_path) => context
+            .rare_output_files()
+            .map(|output_file| output_file.unwrap_or(default_path))
+            .collect(),
+        None => Vec::new(),
+    };
+    for path in rare_paths {
+        if let Err(err) = open_for_append(path) {
+            emit_job(&OutputMessage::error(
+                "rare_output_unwritable",
+                format!("cannot write rare wallets to {}: {}", path.display(), err),
+            ));
+            return None;
+        }
+    }
+    let record_paths: Vec<(PathBuf, RecordFormat)> = [
+        (input.jsonl_output_path.as_deref(), RecordFormat::Jsonl),
+        (input.csv_output_path.as_deref(), RecordFormat::Csv),
+    ]
+    .into_iter()
+    .filter_map(|(path, format)| Some((PathBuf::from(path?), format)))
+    .collect();
+    for (path, format) in &record_paths {
+        if let Err(err) = open_for_append(path) {
+            let code = match format {
+                RecordFormat::Jsonl => "jsonl_output_unwritable",
+                RecordFormat::Csv => "csv_output_unwritable",
             };
+            emit_job(&OutputMessage::error(
+                code,
+                format!("cannot write results to {}: {}", path.display(), err),
+            ));
+            return None;
+        }
+    }
 
-            if let Ok(json) = serde_json::to_string(&msg) {
-                let mut stdout = io::stdout();
-                let _ = writeln!(stdout, "{}", json);
-                let _ = stdout.flush();
+    let redact_private_key = input.redact_private_key.unwrap_or(false);
+    if redact_private_key && out_dir.is_none() && record_paths.is_empty() {
+        emit_job(&OutputMessage::error(
+            "redact_without_output",
+            "redactPrivateKey needs outDir, jsonlOutputPath or csvOutputPath, \
+             or found keys would be lost",
+        ));
+        return None;
+    }
+
+    let qr_private_key = input.qr_private_key.unwrap_or(false);
+    if qr_private_key && redact_private_key && input.qr_out_dir.is_none() {
+        emit_job(&OutputMessage::error(
+            "invalid_qr_options",
+            "qrPrivateKey would print the key to stderr despite redactPrivateKey; \
+             set qrOutDir to save it as a PNG instead",
+        ));
+        return None;
+    }
+    let mut qr = None;
+    if input.qr.unwrap_or(false) || input.qr_out_dir.is_some() {
+        let dir = input.qr_out_dir.as_deref().map(PathBuf::from);
+        if let Some(dir) = &dir {
+            if let Err(err) = fs::create_dir_all(dir) {
+                emit_job(&OutputMessage::error(
+                    "qr_out_dir_unwritable",
+                    format!("cannot create QR output directory {}: {}", dir.display(), err),
+                ));
+                return None;
             }
         }
+        qr = Some(QrOutput {
+            dir,
+            private_key: qr_private_key,
+        });
+    }
 
-        if address_bytes.starts_with(&job_context_ref.prefix_bytes) {
-            let secret_bytes_key = signing_key.to_bytes();
-            let private_key = encode_private_key(&secret_bytes_key, public_key_bytes);
-            
-            let msg = OutputMessage::Found {
-                address,
-                private_key,
-                attempts,
-            };
+    let passphrase = input
+        .passphrase
+        .clone()
+        .or_else(|| env::var(seal::PASSPHRASE_ENV).ok())
+        .map(Zeroizing::new);
+    let sealer = match passphrase.as_deref().map(|p| KeySealer::new(p)).transpose() {
+        Ok(sealer) => sealer,
+        Err(message) => {
+            emit_job(&OutputMessage::error("key_encryption_failed", message));
+            return None;
+        }
+    };
+
+    // Any one prefix completes the job, so their per-candidate hit
+    // probabilities add up.
+    let prefixes = context.prefixes();
+    let mut hit_probability = 0.0;
+    for prefix in &prefixes {
+        let estimate = estimate_attempts(prefix);
+        hit_probability += 1.0 / estimate.corrected;
+        emit_notice(&OutputMessage::Estimate {
+            prefix: prefix.clone(),
+            expected_attempts: estimate.corrected,
+            naive_expected_attempts: estimate.naive,
+        });
+    }
+    let expected_attempts = (hit_probability > 0.0).then(|| 1.0 / hit_probability);
+    if let Some(warning) = impractical_search_warning(expected_attempts) {
+        emit_notice(&warning);
+    }
+
+    let pool = match workers.lease(context.threads()) {
+        Ok(pool) => pool,
+        Err(err) => {
+            emit_job(&OutputMessage::error(
+                "thread_pool_failed",
+                format!("could not start worker threads: {}", err),
+            ));
+            return None;
+        }
+    };
+
+    emit_notice(&OutputMessage::Started {
+        prefix: input.prefix.as_deref().map(|p| p.trim().to_string()),
+        prefixes: prefixes.clone(),
+        threads: context.threads(),
+        expected_attempts,
+        max_attempts: context.max_attempts(),
+        timeout_ms: input.timeout_ms,
+        count: context.target_matches(),
+    });
+
+    let report_interval = (!quiet).then(|| {
+        Duration::from_millis(
+            input
+                .report_interval_ms
+                .unwrap_or(REPORT_INTERVAL_MS)
+                .clamp(MIN_REPORT_INTERVAL_MS, MAX_REPORT_INTERVAL_MS),
+        )
+    });
+    let job = Job {
+        id: job_id,
+        context,
+        rare_output_path,
+        out_dir,
+        expected_attempts,
+        report_interval,
+        quiet,
+        attempts: Arc::new(AtomicU64::new(0)),
+        record_paths,
+        redact_private_key,
+        sealer,
+        qr,
+    };
+    let attempts = Arc::clone(&job.attempts);
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    let cancel_flag_clone = Arc::clone(&cancel_flag);
+    let supervisor = thread::spawn(move || run_job(&pool, job, cancel_flag_clone));
+
+    Some(JobHandle {
+        cancel_flag,
+        supervisor,
+        prefixes,
+        started: Instant::now(),
+        attempts,
+    })
+}
 
-            if let Ok(json) = serde_json::to_string(&msg) {
-                let mut stdout = io::stdout();
-                let _ = writeln!(stdout, "{}", json);
-                let _ = stdout.flush();
+/// Starts a benchmark that grinds keys with no matching for `seconds`.
+fn start_benchmark(
+    job_id: String,
+    seconds: u64,
+    num_threads: usize,
+    rng: RngKind,
+    workers: &WorkerPool,
+) -> Option<JobHandle> {
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    let cancel_flag_clone = Arc::clone(&cancel_flag);
+    let attempts = Arc::new(AtomicU64::new(0));
+    let attempts_clone = Arc::clone(&attempts);
+    let pool = match workers.lease(num_threads) {
+        Ok(pool) => pool,
+        Err(err) => {
+            emit_for(
+                Some(&job_id),
+                &OutputMessage::error(
+                    "thread_pool_failed",
+                    format!("could not start worker threads: {}", err),
+                ),
+            );
+            return None;
+        }
+    };
+
+    let supervisor = thread::spawn(move || {
+        let _span = tracing::info_span!("benchmark", id = %job_id).entered();
+        let duration = Duration::from_secs(seconds);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let bench_start = Instant::now();
+
+        let seed_error = Mutex::new(None);
+        pool.in_place_scope(|scope| {
+            for _ in 0..num_threads {
+                let stop_flag = Arc::clone(&stop_flag);
+                let attempts = Arc::clone(&attempts);
+                let seed_error = &seed_error;
+                scope.spawn(move |_| {
+                    if let Err(err) = benchmark_keygen(rng, &stop_flag, &attempts) {
+                        stop_flag.store(true, Ordering::Relaxed);
+                        seed_error
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .get_or_insert(err);
+                    }
+                });
             }
 
+            while bench_start.elapsed() < duration
+                && !cancel_flag_clone.load(Ordering::Relaxed)
+                && !stop_flag.load(Ordering::Relaxed)
+            {
+                thread::sleep(Duration::from_millis(50));
+            }
             stop_flag.store(true, Ordering::Relaxed);
+        });
+        if let Some(err) = seed_error.into_inner().unwrap_or_else(PoisonError::into_inner) {
+            emit_for(
+                Some(&job_id),
+                &OutputMessage::error(
+                    "rng_seed_failed",
+                    format!("a benchmark thread could not seed its RNG: {}", err),
+                ),
+            );
+            return;
+        }
+
+        let total = attempts.load(Ordering::Relaxed);
+        emit_for(
+            Some(&job_id),
+            &OutputMessage::Benchmark {
+                threads: num_threads,
+                total,
+                rate: total as f64 / bench_start.elapsed().as_secs_f64(),
+            },
+        );
+    });
+
+    Some(JobHandle {
+        cancel_flag,
+        supervisor,
+        prefixes: Vec::new(),
+        started: Instant::now(),
+        attempts: attempts_clone,
+    })
+}
+
+/// The ed25519 + base58 half of `generate_vanity`, without any matching.
+fn benchmark_keygen(
+    rng: RngKind,
+    stop_flag: &AtomicBool,
+    attempts: &AtomicU64,
+) -> Result<(), rand::Error> {
+    let mut rng = rng.build(None)?;
+    let mut secret_bytes = Zeroizing::new([0u8; 32]);
+    let mut address_buf = [0u8; MAX_ADDRESS_LEN];
+    let mut local = 0u64;
+
+    while !stop_flag.load(Ordering::Relaxed) {
+        rand::RngCore::fill_bytes(&mut rng, &mut secret_bytes[..]);
+        let signing_key = SigningKey::from_bytes(&secret_bytes);
+        let address = encode_address(signing_key.verifying_key().as_bytes(), &mut address_buf);
+        std::hint::black_box(address);
+        local += 1;
+        // Publish in batches so status reports move without a shared
+        // counter on every key.
+        if local == BENCHMARK_BATCH {
+            attempts.fetch_add(local, Ordering::Relaxed);
+            local = 0;
+        }
+    }
+
+    attempts.fetch_add(local, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Runs `job` to completion, reporting its progress and how it ended.
+fn run_job(pool: &rayon::ThreadPool, job: Job, cancel_flag: Arc<AtomicBool>) {
+    let _span = tracing::info_span!("job", id = %job.id).entered();
+    let job_start = Instant::now();
+    let matches = AtomicU64::new(0);
+    let on_event = |event: SearchEvent| match event {
+        SearchEvent::Found(found) => {
+            #[cfg(feature = "metrics")]
+            metrics::add_match();
+            matches.fetch_add(1, Ordering::Relaxed);
+            job.emit_found(found)
+        }
+        SearchEvent::Rare(hit) => job.emit_rare(hit),
+        SearchEvent::Error(err) => job.emit(&err.into()),
+    };
+
+    let mut last_report = Instant::now();
+    let mut last_attempts = 0u64;
+    let mut last_thread_attempts = vec![0u64; job.context.threads()];
+    #[cfg(feature = "metrics")]
+    let mut metered_attempts = 0u64;
+    let mut on_tick = |stats: &SearchStats| {
+        if STDOUT_CLOSED.load(Ordering::Relaxed) {
+            cancel_flag.store(true, Ordering::Relaxed);
+        }
+        job.attempts.store(stats.attempts(), Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            let attempts = stats.attempts();
+            metrics::add_attempts(attempts - metered_attempts);
+            metered_attempts = attempts;
+        }
+
+        let now = Instant::now();
+        let since_report = now.duration_since(last_report);
+        if job.report_interval.is_none_or(|interval| since_report < interval) {
+            return;
+        }
+
+        let total_attempts = stats.attempts();
+        let rate = (total_attempts - last_attempts) as f64 / since_report.as_secs_f64();
+        let eta_seconds = job
+            .expected_attempts
+            .filter(|_| rate > 0.0)
+            .map(|expected| expected / rate);
+        let percent = job
+            .expected_attempts
+            .map(|expected| (total_attempts as f64 / expected * 100.0).min(MAX_PROGRESS_PERCENT));
+        job.emit(&OutputMessage::Progress {
+            tid: 0,
+            scope: ProgressScope::Total,
+            attempts: total_attempts,
+            rate: Some(rate),
+            eta_seconds,
+            percent,
+        });
+        for (tid, attempts) in stats.thread_attempts().enumerate() {
+            let thread_rate =
+                (attempts - last_thread_attempts[tid]) as f64 / since_report.as_secs_f64();
+            last_thread_attempts[tid] = attempts;
+            job.emit(&OutputMessage::Progress {
+                tid,
+                scope: ProgressScope::Thread,
+                attempts,
+                rate: Some(thread_rate),
+                eta_seconds: None,
+                percent: None,
+            });
+        }
+        last_report = now;
+        last_attempts = total_attempts;
+    };
+
+    let summary = match run_search_in(pool, &job.context, &cancel_flag, &on_event, &mut on_tick) {
+        Ok(summary) => summary,
+        Err(err) => {
+            job.emit(&err.into());
+            // The search's own totals went with it; report the last ones seen.
+            SearchSummary {
+                reason: StopReason::Failed,
+                attempts: job.attempts.load(Ordering::Relaxed),
+                matches: matches.load(Ordering::Relaxed),
+                elapsed: job_start.elapsed(),
+            }
+        }
+    };
+
+    match summary.reason {
+        _ if job.quiet => {}
+        StopReason::Cancelled => job.emit(&OutputMessage::Stopped {
+            attempts: summary.attempts,
+        }),
+        StopReason::Timeout => job.emit(&OutputMessage::Timeout {
+            attempts: summary.attempts,
+            elapsed_ms: summary.elapsed.as_millis() as u64,
+        }),
+        StopReason::Exhausted => job.emit(&OutputMessage::Exhausted {
+            attempts: summary.attempts,
+        }),
+        _ => {}
+    }
+
+    job.emit(&OutputMessage::Summary {
+        reason: summary.reason,
+        attempts: summary.attempts,
+        elapsed_ms: summary.elapsed.as_millis() as u64,
+        rate: summary.attempts as f64 / summary.elapsed.as_secs_f64(),
+        matches: summary.matches,
+    });
+}
+
+/// Starts `input` as a benchmark or a search, whichever it asks for.
+fn start_request(
+    job_id: String,
+    input: InputMessage,
+    config: Option<&Vec<PatternConfig>>,
+    workers: &WorkerPool,
+) -> Option<JobHandle> {
+    if input.benchmark.unwrap_or(false) {
+        let seconds = input.seconds.unwrap_or(DEFAULT_BENCHMARK_SECONDS).max(1);
+        let threads = resolve_thread_count(input.threads);
+        let rng = input.rng.unwrap_or_default();
+        return start_benchmark(job_id, seconds, threads, rng, workers);
+    }
+    start_job(job_id, input, config, workers)
+}
+
+/// Runs the job, or with `--prefix-file` the jobs, that `cli` describes one
+/// after another. Returns false if any of them could not start.
+fn run_cli(mut cli: Cli, config: Option<&Vec<PatternConfig>>, workers: &WorkerPool) -> bool {
+    let Some(path) = cli.prefix_file.take() else {
+        return run_to_completion("job-1".to_string(), cli.into(), config, workers);
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            emit(&OutputMessage::error(
+                "prefix_file_unreadable",
+                format!("cannot read {}: {}", path.display(), err),
+            ));
+            return false;
+        }
+    };
+
+    let base: InputMessage = cli.into();
+    let mut all_started = true;
+    for (index, line) in content.lines().enumerate() {
+        let prefix = line.trim();
+        if prefix.is_empty() || prefix.starts_with('#') {
+            continue;
+        }
+        let input = InputMessage {
+            prefixes: Some(vec![prefix.to_string()]),
+            ..base.clone()
+        };
+        all_started &= run_to_completion(format!("line-{}", index + 1), input, config, workers);
+    }
+    all_started
+}
+
+/// Starts `input` and waits for it to finish; false if it never started.
+fn run_to_completion(
+    job_id: String,
+    input: InputMessage,
+    config: Option<&Vec<PatternConfig>>,
+    workers: &WorkerPool,
+) -> bool {
+    match start_request(job_id, input, config, workers) {
+        Some(job) => {
+            let _ = job.supervisor.join();
+            true
+        }
+        None => false,
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let format = cli.format;
+    // Logs go to stderr so stdout stays pure protocol; silent unless RUST_LOG is set.
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("off")),
+        )
+        .with_writer(io::stderr)
+        .init();
+    let rotation = RareRotation {
+        max_bytes: cli.rare_file_max_bytes,
+        keep: cli.rare_file_rotations,
+    };
+    let writer = spawn_stdout_writer(format, rotation);
+    #[cfg(feature = "metrics")]
+    match metrics::spawn_server() {
+        Ok(port) => tracing::info!(port, "serving metrics"),
+        Err(err) => emit(&OutputMessage::error(
+            "metrics_unavailable",
+            format!("could not start the metrics endpoint: {}", err),
+        )),
+    }
+    let workers = WorkerPool::new();
+    let config_path = cli
+        .config
+        .clone()
+        .or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from));
+    let mut config = load_config(config_path.as_deref()).unwrap_or_default();
+    if cli.is_one_shot() {
+        let all_started = run_cli(cli, config.as_ref(), &workers);
+        workers.drain();
+        shutdown_writer(writer);
+        process::exit(if all_started { 0 } else { 1 });
+    }
+    emit(&version_message());
+    emit(&OutputMessage::config_loaded(config.as_ref()));
+    let input_commands = spawn_stdin_reader(format);
+    let mut jobs: HashMap<String, JobHandle> = HashMap::new();
+    let mut next_job_number = 0u64;
+    // The loop's `recv_timeout` wakes it at least every 100 ms, which bounds
+    // how finely heartbeats can be spaced.
+    let heartbeat = cli.heartbeat_ms.map(|ms| Duration::from_millis(ms.max(100)));
+    let mut idle_since = Some(Instant::now());
+    let mut last_heartbeat = Instant::now();
+
+    loop {
+        let command = match input_commands.recv_timeout(Duration::from_millis(100)) {
+            Ok(command) => Some(command),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if STDOUT_CLOSED.load(Ordering::Relaxed) {
+            for job in jobs.values() {
+                job.cancel_flag.store(true, Ordering::Relaxed);
+            }
             break;
         }
+
+        let finished: Vec<String> = jobs
+            .iter()
+            .filter(|(_, job)| job.supervisor.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in finished {
+            if let Some(job) = jobs.remove(&id) {
+                let _ = job.supervisor.join();
+            }
+        }
+
+        if !jobs.is_empty() {
+            idle_since = None;
+        } else {
+            let since = *idle_since.get_or_insert_with(Instant::now);
+            if heartbeat.is_some_and(|every| last_heartbeat.max(since).elapsed() >= every) {
+                emit(&OutputMessage::Idle {
+                    since_ms: since.elapsed().as_millis() as u64,
+                });
+                last_heartbeat = Instant::now();
+            }
+        }
+
+        let input = match command {
+            Some(InputCommand::Message(input)) => *input,
+            Some(InputCommand::Stop) => {
+                for job in jobs.values() {
+                    job.cancel_flag.store(true, Ordering::Relaxed);
+                }
+                break;
+            }
+            Some(InputCommand::CancelAll) => {
+                for job in jobs.values() {
+                    job.cancel_flag.store(true, Ordering::Relaxed);
+                }
+                continue;
+            }
+            Some(InputCommand::Invalid(message)) => {
+                tracing::warn!(%message, "unparseable input");
+                emit(&OutputMessage::error("invalid_input", message));
+                continue;
+            }
+            None => continue,
+        };
+
+        if let Some(cancel_id) = input.cancel.as_deref() {
+            match jobs.get(cancel_id) {
+                Some(job) => job.cancel_flag.store(true, Ordering::Relaxed),
+                None => emit_for(
+                    Some(cancel_id),
+                    &OutputMessage::error(
+                        "unknown_job",
+                        format!("no running job with id {:?}", cancel_id),
+                    ),
+                ),
+            }
+            continue;
+        }
+
+        if input.status.unwrap_or(false) {
+            let mut statuses: Vec<JobStatus> =
+                jobs.iter().map(|(id, job)| job.status(id)).collect();
+            statuses.sort_by_key(|status| Reverse(status.elapsed_ms));
+            emit(&OutputMessage::StatusReport { jobs: statuses });
+            continue;
+        }
+
+        if input.list.unwrap_or(false) {
+            let mut ids: Vec<String> = jobs.keys().cloned().collect();
+            ids.sort();
+            emit(&OutputMessage::JobList { ids });
+            continue;
+        }
+
+        if input.version.unwrap_or(false) {
+            emit(&version_message());
+            continue;
+        }
+
+        if input.config_info.unwrap_or(false) {
+            emit(&OutputMessage::config_loaded(config.as_ref()));
+            continue;
+        }
+
+        // Running jobs keep the rules they started with.
+        if input.reload_config.unwrap_or(false) {
+            if let Ok(reloaded) = load_config(config_path.as_deref()) {
+                config = reloaded;
+                emit(&OutputMessage::config_loaded(config.as_ref()));
+            }
+            continue;
+        }
+
+        let job_id = match input.job_id.clone() {
+            Some(id) => id,
+            None => {
+                next_job_number += 1;
+                format!("job-{}", next_job_number)
+            }
+        };
+        if jobs.contains_key(&job_id) {
+            emit_for(
+                Some(&job_id),
+                &OutputMessage::error(
+                    "duplicate_job_id",
+                    format!("job {:?} is already running", job_id),
+                ),
+            );
+            continue;
+        }
+
+        if let Some(job) = start_request(job_id.clone(), input, config.as_ref(), &workers) {
+            jobs.insert(job_id, job);
+        }
+    }
+
+    for (_, job) in jobs.drain() {
+        let _ = job.supervisor.join();
     }
+    workers.drain();
+    shutdown_writer(writer);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::Once;
+
+    /// Everything the writer thread has written, shared by every test in
+    /// this binary; tests tell their messages apart by job id.
+    static CAPTURED: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            CAPTURED.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Starts `input` as job `job_id` on `workers`, its output going to
+    /// `CAPTURED`.
+    fn start(workers: &WorkerPool, job_id: &str, input: Value) -> JobHandle {
+        start_message(workers, job_id, serde_json::from_value(input).expect("valid input"))
+    }
+
+    fn start_message(workers: &WorkerPool, job_id: &str, input: InputMessage) -> JobHandle {
+        capture_output();
+        start_request(job_id.to_string(), input, None, workers).expect("job started")
+    }
+
+    /// Points the writer thread at `CAPTURED`, once per test binary.
+    fn capture_output() {
+        static WRITER: Once = Once::new();
+        WRITER.call_once(|| {
+            let rotation = RareRotation {
+                max_bytes: None,
+                keep: 0,
+            };
+            spawn_writer(|| Capture, WireFormat::Json, rotation);
+        });
+    }
+
+    /// The complete messages written so far for `job_id`.
+    fn messages(job_id: &str) -> Vec<Value> {
+        let captured = CAPTURED.lock().unwrap();
+        let text = String::from_utf8_lossy(&captured);
+        let complete = &text[..text.rfind('\n').map_or(0, |end| end + 1)];
+        complete
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).expect("JSON output"))
+            .filter(|message| message["jobId"] == job_id)
+            .collect()
+    }
+
+    /// Waits up to ten seconds for a message from `job_id` that `pred`
+    /// accepts.
+    fn wait_for(job_id: &str, pred: impl Fn(&Value) -> bool) -> Option<Value> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some(message) = messages(job_id).into_iter().find(&pred) {
+                return Some(message);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+
+    /// Latest job-wide attempt count `job_id` has reported.
+    fn total_attempts(job_id: &str) -> u64 {
+        messages(job_id)
+            .iter()
+            .filter(|message| message["type"] == "progress" && message["scope"] == "total")
+            .filter_map(|message| message["attempts"].as_u64())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Runs `input` as job `job_id` to the end and returns its `summary`.
+    fn run(workers: &WorkerPool, job_id: &str, input: Value) -> Value {
+        start(workers, job_id, input).supervisor.join().unwrap();
+        wait_for(job_id, |m| m["type"] == "summary").expect("summary")
+    }
+
+    #[test]
+    fn sequential_jobs_count_only_their_own_attempts() {
+        let workers = WorkerPool::new();
+        let first = run(&workers, "seq-1", json!({"prefix": "zzzzzzzz", "maxAttempts": 5000}));
+        let second = run(&workers, "seq-2", json!({"prefix": "zzzzzzzz", "maxAttempts": 50}));
+        assert!(first["attempts"].as_u64().unwrap() >= 5000);
+        let attempts = second["attempts"].as_u64().unwrap();
+        assert!((50..1000).contains(&attempts), "second job reported {}", attempts);
+    }
+
+    /// A pipe whose reader has gone, counting the writes it refused.
+    struct ClosedPipe(Arc<AtomicUsize>);
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn broken_pipe_marks_the_sink_closed() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+        let writes = Arc::new(AtomicUsize::new(0));
+        let mut sink = StdoutSink::new(ClosedPipe(Arc::clone(&writes)), WireFormat::Json, &CLOSED);
+        let message = OutputMessage::Stopped { attempts: 1 };
+
+        sink.write(None, &message);
+        sink.flush();
+        assert!(CLOSED.load(Ordering::Relaxed));
+        let refused = writes.load(Ordering::Relaxed);
+        sink.write(None, &message);
+        sink.flush();
+        assert_eq!(writes.load(Ordering::Relaxed), refused, "kept writing to a closed pipe");
+    }
+
+    /// Set in the copy of this test binary that `closed_stdout_stops_running_jobs`
+    /// starts.
+    const CLOSED_STDOUT_CHILD: &str = "VANITY_GEN_TEST_CLOSED_STDOUT";
+
+    #[test]
+    fn closed_stdout_stops_running_jobs() {
+        // A closed stdout stops every job in the process, so this runs alone
+        // in a child process where the writer's only output is a dead pipe.
+        if env::var_os(CLOSED_STDOUT_CHILD).is_none() {
+            let output = process::Command::new(env::current_exe().unwrap())
+                .args(["--exact", "tests::closed_stdout_stops_running_jobs"])
+                .env(CLOSED_STDOUT_CHILD, "1")
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(output.status.success(), "child test failed:\n{}", stdout);
+            return;
+        }
+
+        let rotation = RareRotation {
+            max_bytes: None,
+            keep: 0,
+        };
+        spawn_writer(|| ClosedPipe(Arc::new(AtomicUsize::new(0))), WireFormat::Json, rotation);
+        let input = serde_json::from_value(json!({"prefix": "zzzzzzzz"})).unwrap();
+        let job = start_request("orphaned".to_string(), input, None, &WorkerPool::new())
+            .expect("job started");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !job.supervisor.is_finished() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(STDOUT_CLOSED.load(Ordering::Relaxed));
+        assert!(job.cancel_flag.load(Ordering::Relaxed), "the job was not told to stop");
+        assert!(job.supervisor.is_finished(), "the job kept running");
+    }
+
+    #[test]
+    fn cancelling_one_job_leaves_the_other_running() {
+        // Each job asks for every thread it can have.
+        let search = json!({
+            "prefix": "zzzzzzzz",
+            "threads": resolve_thread_count(Some(usize::MAX)),
+            "reportIntervalMs": 50,
+        });
+        let workers = WorkerPool::new();
+        let kept = start(&workers, "cancel-kept", search.clone());
+        let cancelled = start(&workers, "cancel-dropped", search);
+
+        // Waits rather than joins: a job whose workers never got threads
+        // would never finish.
+        cancelled.cancel_flag.store(true, Ordering::Relaxed);
+        let stopped = wait_for("cancel-dropped", |m| m["type"] == "stopped");
+        let at_cancel = total_attempts("cancel-kept");
+        let later = wait_for("cancel-kept", |m| {
+            m["type"] == "progress" && m["attempts"].as_u64() > Some(at_cancel)
+        });
+        kept.cancel_flag.store(true, Ordering::Relaxed);
+        assert!(stopped.is_some(), "the cancelled job never stopped");
+        assert!(later.is_some(), "the remaining job stopped making progress");
+        cancelled.supervisor.join().unwrap();
+        kept.supervisor.join().unwrap();
+    }
+
+    #[test]
+    fn redacted_keys_reach_only_the_key_file() {
+        let dir = env::temp_dir().join(format!("vanity_gen-redact-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = json!({
+            "prefix": "2",
+            "outDir": dir.to_str().unwrap(),
+            "redactPrivateKey": true,
+        });
+        run(&WorkerPool::new(), "redact", input);
+        let found = wait_for("redact", |m| m["type"] == "found").expect("found");
+        assert_eq!(found["private_key"], REDACTED);
+
+        let key_file = fs::read_to_string(found["key_file"].as_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let keypair: Vec<u8> = serde_json::from_str(&key_file).expect("a solana-keygen file");
+        assert_eq!(keypair.len(), 64);
+        let signing_key = SigningKey::from_bytes(keypair[..32].try_into().unwrap());
+        let mut address = [0; MAX_ADDRESS_LEN];
+        let address = encode_address(&signing_key.verifying_key().to_bytes(), &mut address);
+        assert_eq!(found["address"], address);
+    }
+
+    #[test]
+    fn config_loaded_lists_only_usable_rules() {
+        let patterns: Vec<PatternConfig> = serde_json::from_value(json!([
+            {"pattern": "A", "minLength": 4},
+            {"pattern": "B", "minLength": 4, "enabled": false},
+            {"pattern": "C", "minLength": 0},
+            {"pattern": "D", "minLength": 5, "maxLength": 4},
+            {"regex": "(", "minLength": 4},
+        ]))
+        .unwrap();
+        let OutputMessage::ConfigLoaded { patterns } = OutputMessage::config_loaded(Some(&patterns))
+        else {
+            unreachable!()
+        };
+        let listed: Vec<&str> = patterns.iter().map(|p| p.pattern.as_str()).collect();
+        assert_eq!(listed, ["A"]);
+    }
+
+    #[test]
+    fn every_object_on_a_line_starts_a_job() {
+        let line = r#"{"prefix": "2", "maxAttempts": 50} {"prefix": "3", "maxAttempts": 50}"#;
+        let commands = parse_line(line);
+        assert_eq!(commands.len(), 2);
+
+        let workers = WorkerPool::new();
+        for (i, command) in commands.into_iter().enumerate() {
+            let InputCommand::Message(input) = command else {
+                panic!("object {} did not parse", i);
+            };
+            let job_id = format!("line-{}", i);
+            start_message(&workers, &job_id, *input).supervisor.join().unwrap();
+            let summary = wait_for(&job_id, |m| m["type"] == "summary");
+            assert!(summary.is_some(), "{} never ran", job_id);
+        }
+    }
+
+    #[test]
+    fn timeout_is_reported_after_some_matches() {
+        let input = json!({"prefix": "2", "count": 1_000_000, "timeoutMs": 300});
+        let summary = run(&WorkerPool::new(), "timeout-matched", input);
+        assert!(summary["matches"].as_u64() > Some(0));
+        assert!(messages("timeout-matched").iter().any(|m| m["type"] == "timeout"));
+    }
+
+    #[test]
+    fn skipped_patterns_are_reported_for_their_job() {
+        let config: Vec<PatternConfig> =
+            serde_json::from_value(json!([{"regex": "(", "minLength": 4}])).unwrap();
+        let input = serde_json::from_value(json!({"prefix": "2"})).unwrap();
+        capture_output();
+        let job = start_request("skipped".to_string(), input, Some(&config), &WorkerPool::new());
+        job.expect("job started").supervisor.join().unwrap();
+        let error = wait_for("skipped", |m| m["type"] == "error");
+        assert_eq!(error.expect("a job error")["code"], "invalid_pattern");
+    }
+
+    #[test]
+    fn impractical_searches_get_a_warning() {
+        let expected = |prefix: &str| Some(estimate_attempts(prefix).corrected);
+        assert!(impractical_search_warning(expected("SoL")).is_none());
+        assert!(matches!(
+            impractical_search_warning(expected("SoLanaVanity")),
+            Some(OutputMessage::Warning { .. })
+        ));
+        assert!(impractical_search_warning(None).is_none());
+    }
+}
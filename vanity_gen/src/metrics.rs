@@ -0,0 +1,105 @@
+//! Prometheus metrics over plain HTTP, behind the `metrics` feature.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Port used when `VANITY_GEN_METRICS_PORT` is unset.
+const DEFAULT_PORT: u16 = 9464;
+/// Address used when `VANITY_GEN_METRICS_ADDR` is unset: this machine only,
+/// since the process holds private keys.
+const DEFAULT_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Counts across every search job since the process started.
+static ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+static MATCHES: AtomicU64 = AtomicU64::new(0);
+
+pub fn add_attempts(attempts: u64) {
+    ATTEMPTS.fetch_add(attempts, Ordering::Relaxed);
+}
+
+pub fn add_match() {
+    MATCHES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Serves the metrics on `VANITY_GEN_METRICS_ADDR` and
+/// `VANITY_GEN_METRICS_PORT` from a background thread.
+pub fn spawn_server() -> io::Result<u16> {
+    let addr = match env::var("VANITY_GEN_METRICS_ADDR") {
+        Ok(addr) => addr.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid VANITY_GEN_METRICS_ADDR {:?}", addr),
+            )
+        })?,
+        Err(_) => DEFAULT_ADDR,
+    };
+    let port = match env::var("VANITY_GEN_METRICS_PORT") {
+        Ok(port) => port.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid VANITY_GEN_METRICS_PORT {:?}", port),
+            )
+        })?,
+        Err(_) => DEFAULT_PORT,
+    };
+    let listener = TcpListener::bind((addr, port))?;
+
+    thread::spawn(move || {
+        let last_scrape = Arc::new(Mutex::new((Instant::now(), 0u64)));
+        // A thread per scrape, so a client that is slow to send its request
+        // can't hold up the others.
+        for stream in listener.incoming().flatten() {
+            let last_scrape = Arc::clone(&last_scrape);
+            thread::spawn(move || serve(stream, &last_scrape));
+        }
+    });
+    Ok(port)
+}
+
+/// Answers one scrape. Every path gets the metrics; the keys/sec gauge
+/// covers the time since the previous scrape.
+fn serve(mut stream: TcpStream, last_scrape: &Mutex<(Instant, u64)>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+
+    let (attempts, rate) = {
+        let mut last_scrape = last_scrape.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let attempts = ATTEMPTS.load(Ordering::Relaxed);
+        let elapsed = now.duration_since(last_scrape.0).as_secs_f64();
+        let rate = (attempts - last_scrape.1) as f64 / elapsed;
+        *last_scrape = (now, attempts);
+        (attempts, rate)
+    };
+
+    let body = format!(
+        "# HELP vanity_gen_attempts_total Keys generated by search jobs.\n\
+         # TYPE vanity_gen_attempts_total counter\n\
+         vanity_gen_attempts_total {}\n\
+         # HELP vanity_gen_matches_total Keys that matched their job's criteria.\n\
+         # TYPE vanity_gen_matches_total counter\n\
+         vanity_gen_matches_total {}\n\
+         # HELP vanity_gen_keys_per_second Keys generated per second since the last scrape.\n\
+         # TYPE vanity_gen_keys_per_second gauge\n\
+         vanity_gen_keys_per_second {}\n",
+        attempts,
+        MATCHES.load(Ordering::Relaxed),
+        rate
+    );
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,79 @@
+//! Passphrase encryption for private keys written to files.
+//!
+//! A sealed secret is a single self-describing token,
+//!
+//! `enc:scrypt:<log_n>:<r>:<p>:<salt>:<nonce>:<ciphertext>`
+//!
+//! with the salt, 24-byte nonce and ciphertext in hex. The key is 32 bytes of
+//! `scrypt(passphrase, salt, log_n, r, p)`; the ciphertext is the UTF-8
+//! secret under XChaCha20-Poly1305 with no associated data, tag appended.
+//! Each job derives one key under one salt, so its tokens share a header.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt::Write;
+use zeroize::Zeroizing;
+
+/// Environment variable read when a job doesn't carry its own passphrase.
+pub const PASSPHRASE_ENV: &str = "VANITY_GEN_PASSPHRASE";
+
+/// scrypt cost: 2^15 rounds with r = 8 takes 32 MiB and a fraction of a
+/// second, once per job.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+pub struct KeySealer {
+    cipher: XChaCha20Poly1305,
+    /// Everything before the nonce, shared by every token this sealer makes.
+    header: String,
+}
+
+impl KeySealer {
+    /// Derives a key from `passphrase` under a fresh random salt.
+    pub fn new(passphrase: &str) -> Result<Self, String> {
+        if passphrase.is_empty() {
+            return Err("passphrase must not be empty".to_string());
+        }
+        let mut salt = [0u8; 16];
+        OsRng
+            .try_fill_bytes(&mut salt)
+            .map_err(|err| format!("could not generate a salt: {}", err))?;
+        let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, 32)
+            .map_err(|err| err.to_string())?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut key[..])
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new((&*key).into()),
+            header: format!(
+                "enc:scrypt:{}:{}:{}:{}",
+                SCRYPT_LOG_N,
+                SCRYPT_R,
+                SCRYPT_P,
+                hex(&salt)
+            ),
+        })
+    }
+
+    /// Encrypts `secret` under a fresh nonce.
+    pub fn seal(&self, secret: &str) -> String {
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), secret.as_bytes())
+            .expect("a private key is far below the XChaCha20-Poly1305 length limit");
+        format!("{}:{}:{}", self.header, hex(&nonce), hex(&ciphertext))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}